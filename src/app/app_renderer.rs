@@ -7,6 +7,7 @@ use crate::renderer::resources::shader::{MAX_WORKGROUP_DIM_1D, MAX_WORKGROUP_DIM
 use crate::renderer::resources::texture::get_atlas_image;
 use crate::renderer::resources::vx_buffer::VxBuffer;
 use crate::vtypes::Camera;
+use crate::world::time::Sky;
 use crate::world::chunk::VoxelChunk;
 use glam::IVec3;
 use std::borrow::Cow;
@@ -83,11 +84,19 @@ impl AppRenderer<'_> {
         main_camera: &Camera,
         voxel_culling_distance: u32,
         window_size: PhysicalSize<u32>,
+        sky: &Sky,
     ) -> Result<(), wgpu::SurfaceError> {
         let frame = self.renderer.surface.get_current_texture()?;
         let view = frame.texture.create_view(&Default::default());
 
-        let camera_view = VxGPUCamera::new(main_camera, voxel_culling_distance, window_size);
+        let camera_view =
+            VxGPUCamera::new(main_camera, voxel_culling_distance, window_size, sky);
+        let clear_color = wgpu::Color {
+            r: sky.color.x as f64,
+            g: sky.color.y as f64,
+            b: sky.color.z as f64,
+            a: 1.0,
+        };
 
         self.renderer.write_buffer(
             &self.view_projection_buffer,
@@ -96,9 +105,12 @@ impl AppRenderer<'_> {
         );
 
         {
-            let mut render_pass =
-                self.renderer
-                    .begin_render_pass(&mut encoder, "Main Render Pass", &view);
+            let mut render_pass = self.renderer.begin_render_pass(
+                &mut encoder,
+                "Main Render Pass",
+                &view,
+                clear_color,
+            );
             self.render_chunks(&mut render_pass);
         }
 
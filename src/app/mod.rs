@@ -1,10 +1,17 @@
 pub mod app_renderer;
 
 use crate::compute::geo::Frustum;
+use crate::compute::timestep::FixedTimestep;
+use crate::compute::utils::spawn_console_input;
 use crate::vtypes::{Scene, Voxer, VoxerObject};
-use crate::world::{ClientWorld, ClientWorldConfig, ServerWorld, CHUNK_DIM};
+use crate::world::physics::{
+    MovementInput, MovementMode, PHYSICS_TICK_RATE, PhysicsConfig, PlayerBody,
+};
+use crate::world::{ClientWorld, ClientWorldConfig, NetworkHandle, ServerThread, CHUNK_DIM};
 use crate::{call_every, vtypes};
+use crossbeam::channel;
 use std::sync::Arc;
+use std::time::Duration;
 use winit::application::ApplicationHandler;
 use winit::dpi::PhysicalSize;
 use winit::event::{DeviceEvent, DeviceId, ElementState, WindowEvent};
@@ -12,25 +19,37 @@ use winit::event_loop::ActiveEventLoop;
 use winit::keyboard::KeyCode;
 use winit::window::{CursorGrabMode, Window};
 
+// server corrections further than this are snapped to instead of smoothed
+const MAX_SMOOTHED_CORRECTION: f32 = 2.0;
+const CORRECTION_SMOOTHING_RATE: f32 = 12.0;
+
 #[derive(Default)]
 pub struct AppDebug {}
 
 pub struct App<'a> {
     pub window: Option<Arc<Window>>,
     pub v: Voxer, // voxer engine; input, time, camera, etc
-    pub server: ServerWorld,
+    pub server: ServerThread, // ticks on its own, the client reaches it through the network
     pub client: Option<ClientWorld<'a>>,
     pub client_config: ClientWorldConfig,
+    client_network: Option<NetworkHandle>, // handed to the client once the window exists
     pub scene: Scene,
     pub debug: AppDebug,
+    player: PlayerBody,
+    physics_config: PhysicsConfig,
+    physics_step: FixedTimestep,
+    spawned: bool, // physics waits for the server to place the player
+    correction_offset: glam::Vec3, // view offset left over from a smoothed server correction
+    console_input: channel::Receiver<String>, // lines typed into the terminal, sent as chat
 }
 
 impl<'a> App<'a> {
     pub fn new(
         v: Voxer,
-        server: ServerWorld,
+        server: ServerThread,
         scene: Scene,
         client_config: ClientWorldConfig,
+        client_network: NetworkHandle,
     ) -> Self {
         Self {
             window: None,
@@ -38,8 +57,15 @@ impl<'a> App<'a> {
             server,
             client: None,
             client_config,
+            client_network: Some(client_network),
             scene,
             debug: Default::default(),
+            player: PlayerBody::new(glam::Vec3::ZERO, MovementMode::Fly),
+            physics_config: PhysicsConfig::default(),
+            physics_step: FixedTimestep::new(PHYSICS_TICK_RATE, 8),
+            spawned: false,
+            correction_offset: glam::Vec3::ZERO,
+            console_input: spawn_console_input(),
         }
     }
 }
@@ -88,9 +114,10 @@ impl<'a> ApplicationHandler for App<'a> {
         let win_size = window.inner_size();
         let aspect_ratio = win_size.width as f32 / win_size.height as f32;
         self.v.camera.set_aspect_ratio(aspect_ratio);
-        self.v.camera.transform.position = glam::vec3(0.0, 40.0, 0.0);
+        self.v.camera.transform.position = self.player.eye_position();
 
-        let client = ClientWorld::new(window, self.client_config);
+        let network = self.client_network.take().unwrap();
+        let mut client = ClientWorld::new(window, self.client_config.clone(), network);
         client.temp_send_req_conn();
         self.client = Some(client);
     }
@@ -106,7 +133,7 @@ impl<'a> ApplicationHandler for App<'a> {
             _ => return,
         };
         match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::CloseRequested => self.exit(event_loop),
             WindowEvent::RedrawRequested => {
                 let client = self.client.as_mut().unwrap();
                 self.v.time.tick();
@@ -114,8 +141,11 @@ impl<'a> ApplicationHandler for App<'a> {
                 call_every!(WINDOW_TITLE_UPDATE, 200, || {
                     let p = self.v.camera.transform.position;
                     let fps = self.v.time.fps_avg().floor() as u32;
-                    let title =
-                        format!("FPS: {:>4} ({:>8.1},{:>8.1},{:>8.1})", fps, p.x, p.y, p.z,);
+                    let rtt = client.rtt().map_or(0, |rtt| rtt.as_millis());
+                    let title = format!(
+                        "FPS: {:>4} RTT: {:>4}ms ({:>8.1},{:>8.1},{:>8.1})",
+                        fps, rtt, p.x, p.y, p.z,
+                    );
                     window.set_title(&title);
                 });
 
@@ -123,14 +153,16 @@ impl<'a> ApplicationHandler for App<'a> {
                     .renderer
                     .renderer
                     .create_encoder("Main Encoder");
-                client.tick(&mut encoder);
+                client.tick(&mut encoder, self.v.time.dt());
                 let voxel_culling_distance = self.client_config.render_distance * CHUNK_DIM;
                 let window_size = window.inner_size();
+                let sky = client.session.clock.time.sky();
                 let render_result = client.renderer.submit_render_pass(
                     encoder,
                     &self.v.camera,
                     voxel_culling_distance as u32,
                     window_size,
+                    &sky,
                 );
                 render_result.unwrap_or_else(|e| println!("{:?}", e));
             }
@@ -161,7 +193,11 @@ impl<'a> ApplicationHandler for App<'a> {
         }
     }
 
-    fn about_to_wait(&mut self, _: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.server.is_finished() && !event_loop.exiting() {
+            self.exit(event_loop);
+            return;
+        }
         for vo in self.scene.objects.iter_mut() {
             vo.update(&mut self.v);
         }
@@ -179,11 +215,21 @@ impl<'a> ApplicationHandler for App<'a> {
 }
 
 impl<'a> App<'a> {
+    fn exit(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(client) = self.client.as_mut() {
+            client.disconnect();
+            client.flush_capture();
+        }
+        self.server.stop();
+        event_loop.exit();
+    }
+
     fn update(&mut self) {
-        {
+        let movement_input = {
             let input = self.v.input.read();
-            const MOVE_SPEED: f32 = 10.0;
-            let sprint_mul = (1 + input.keyboard.key_down(KeyCode::ShiftLeft) as u32 * 6) as f32;
+            if input.keyboard.key_pressed(KeyCode::KeyF) {
+                self.player.toggle_mode();
+            }
 
             let w = input.keyboard.key_down(KeyCode::KeyW) as i32;
             let a = input.keyboard.key_down(KeyCode::KeyA) as i32;
@@ -191,11 +237,64 @@ impl<'a> App<'a> {
             let d = input.keyboard.key_down(KeyCode::KeyD) as i32;
             let forward_input = w - s;
             let right_input = d - a;
-            let move_vec = forward_input as f32 * self.v.camera.transform.forward()
+            let mut wish_dir = forward_input as f32 * self.v.camera.transform.forward()
                 + right_input as f32 * self.v.camera.transform.right();
-            self.v.camera.transform.position +=
-                move_vec * MOVE_SPEED * sprint_mul * self.v.time.dt();
+            match self.player.mode {
+                MovementMode::Walk => {
+                    // walking moves along the ground regardless of pitch
+                    let forward = self.v.camera.transform.forward().with_y(0.0);
+                    let right = self.v.camera.transform.right().with_y(0.0);
+                    wish_dir = forward_input as f32 * forward.normalize_or_zero()
+                        + right_input as f32 * right.normalize_or_zero();
+                }
+                MovementMode::Fly => {
+                    let up = input.keyboard.key_down(KeyCode::Space) as i32;
+                    let down = input.keyboard.key_down(KeyCode::ControlLeft) as i32;
+                    wish_dir += (up - down) as f32 * glam::Vec3::Y;
+                }
+            }
+            MovementInput {
+                wish_dir,
+                jump: input.keyboard.key_down(KeyCode::Space),
+                sprint: input.keyboard.key_down(KeyCode::ShiftLeft),
+            }
+        };
+
+        let m_client = self.client.as_mut().unwrap();
+        for line in self.console_input.try_iter() {
+            if let Err(e) = m_client.send_chat(&line) {
+                println!("message not sent: {:?}", e);
+            }
         }
+        if let Some(correction) = m_client.take_correction() {
+            let error = self.player.position - correction.position;
+            let snap = correction.snap || error.length() > MAX_SMOOTHED_CORRECTION;
+            self.correction_offset = match snap {
+                true => glam::Vec3::ZERO,
+                false => self.correction_offset + error,
+            };
+            self.player.position = correction.position;
+            self.player.velocity = glam::Vec3::ZERO;
+            self.spawned = true;
+        }
+        let (steps, _) = match self.spawned {
+            true => self
+                .physics_step
+                .advance(Duration::from_secs_f32(self.v.time.dt())),
+            false => (0, false),
+        };
+        let step_dt = self.physics_step.step_secs();
+        for _ in 0..steps {
+            self.player.step(
+                &movement_input,
+                &self.physics_config,
+                step_dt,
+                &m_client.session.chunks,
+            );
+        }
+        // small corrections are applied to the body at once but eased out of the view
+        self.correction_offset *= (-CORRECTION_SMOOTHING_RATE * self.v.time.dt()).exp();
+        self.v.camera.transform.position = self.player.eye_position() + self.correction_offset;
 
         // let culling_camera = &self.v.camera;
         // let safe_voxel_rdist = ((self.client_config.render_distance - 1) * CHUNK_DIM) as f32;
@@ -204,13 +303,14 @@ impl<'a> App<'a> {
         // let safe_culling_vf = Frustum::planes(safe_culling_vp);
         // let camera_position = self.v.camera.transform.position;
 
-        let m_client = self.client.as_mut().unwrap();
         m_client.temp_set_camera(self.v.camera.clone());
+        m_client.set_player_position(
+            self.player.position,
+            self.player.mode == MovementMode::Fly,
+        );
 
         call_every!(CLIENT_POS_SEND, 20, || {
             m_client.temp_send_player_position()
         });
-
-        self.server.tick();
     }
 }
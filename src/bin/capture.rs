@@ -0,0 +1,90 @@
+//! summarises a capture recorded with `--record-server` or `--record-client`, by message type
+use std::collections::BTreeMap;
+use std::time::Duration;
+use voxer::world::{CaptureDirection, CaptureReader};
+
+#[derive(Default)]
+struct MessageStats {
+    count: usize,
+    bytes: usize,
+    min: usize,
+    max: usize,
+}
+
+impl MessageStats {
+    fn add(&mut self, size: usize) {
+        self.min = match self.count {
+            0 => size,
+            _ => self.min.min(size),
+        };
+        self.max = self.max.max(size);
+        self.count += 1;
+        self.bytes += size;
+    }
+}
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: capture <file>");
+        std::process::exit(2);
+    };
+    let reader = CaptureReader::open(&path).unwrap_or_else(|e| {
+        eprintln!("failed to open {path}: {e}");
+        std::process::exit(1);
+    });
+    println!("{path}, recorded on protocol {}", reader.protocol_version);
+
+    let mut stats: BTreeMap<(String, &str), MessageStats> = BTreeMap::new();
+    let mut totals: BTreeMap<&str, MessageStats> = BTreeMap::new();
+    let mut duration = Duration::ZERO;
+    for record in reader {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                // most likely cut short by the recording process ending, summarise what is there
+                println!("capture ends early: {e}");
+                break;
+            }
+        };
+        let direction = match record.direction {
+            CaptureDirection::Sent => "sent",
+            _ => "received",
+        };
+        let tag = record
+            .tag
+            .map_or("unknown".to_string(), |tag| format!("{:?}", tag));
+        stats
+            .entry((tag, direction))
+            .or_default()
+            .add(record.data.len());
+        totals.entry(direction).or_default().add(record.data.len());
+        duration = record.time;
+    }
+
+    println!(
+        "{:<20} {:<9} {:>8} {:>12} {:>8} {:>8} {:>8}",
+        "message", "direction", "count", "bytes", "avg", "min", "max"
+    );
+    for ((tag, direction), s) in stats.iter() {
+        println!(
+            "{:<20} {:<9} {:>8} {:>12} {:>8} {:>8} {:>8}",
+            tag,
+            direction,
+            s.count,
+            s.bytes,
+            s.bytes / s.count,
+            s.min,
+            s.max
+        );
+    }
+    let secs = duration.as_secs_f64().max(f64::EPSILON);
+    for (direction, s) in totals.iter() {
+        println!(
+            "{direction}: {} messages, {} bytes, {:.1} KiB/s over {:.1}s",
+            s.count,
+            s.bytes,
+            s.bytes as f64 / 1024.0 / secs,
+            secs
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! dedicated server without a window or gpu: `cargo run --bin server --no-default-features`
+use crossbeam::channel;
+use glam::USizeVec3;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+use voxer::compute;
+use voxer::compute::utils::spawn_console_input;
+use voxer::world::{
+    ChunkStreamingMode, NetworkHandle, ServerThread, ServerWorld, ServerWorldConfig,
+    TickLoopConfig, WorldConfig,
+};
+
+const USAGE: &str = "usage: server [--bind=<ip>] [--port=<port>] [--world=<dir>] [--seed=<seed>] \
+                     [--simulation-distance=<chunks>] [--tick-rate=<hz>] \
+                     [--chunk-streaming=push|pull] [--op=<ip>]...
+
+--op lets players connecting from <ip> run any command. names aren't authenticated, anyone can
+join under any name, so operators are recognised by their address instead";
+
+struct ServerOptions {
+    bind: IpAddr,
+    port: u16,
+    world_directory: PathBuf,
+    seed: i32,
+    simulation_distance: usize,
+    tick_rate: u32,
+    chunk_streaming: ChunkStreamingMode,
+    operators: Vec<IpAddr>,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3100,
+            world_directory: PathBuf::from("saves/world"),
+            seed: 0,
+            simulation_distance: 24,
+            tick_rate: 60,
+            chunk_streaming: ChunkStreamingMode::Pull,
+            operators: Vec::new(),
+        }
+    }
+}
+
+impl ServerOptions {
+    /// `--name=value` arguments, anything not given keeps its default
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        for arg in args {
+            let (name, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("expected --name=value, got {arg}"))?;
+            let invalid = || format!("invalid {name}: {value}");
+            match name {
+                "--bind" => options.bind = value.parse().map_err(|_| invalid())?,
+                "--port" => options.port = value.parse().map_err(|_| invalid())?,
+                "--world" => options.world_directory = PathBuf::from(value),
+                "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+                "--simulation-distance" => {
+                    options.simulation_distance = value
+                        .parse()
+                        .ok()
+                        .filter(|&distance| distance > 1)
+                        .ok_or_else(invalid)?
+                }
+                "--tick-rate" => {
+                    options.tick_rate = value
+                        .parse()
+                        .ok()
+                        .filter(|&rate| rate > 0)
+                        .ok_or_else(invalid)?
+                }
+                "--chunk-streaming" => {
+                    options.chunk_streaming =
+                        ChunkStreamingMode::parse(value).ok_or_else(invalid)?
+                }
+                "--op" => options
+                    .operators
+                    .push(value.parse().map_err(|_| invalid())?),
+                _ => return Err(format!("unknown option {name}")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn main() {
+    let options = ServerOptions::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n{USAGE}");
+        std::process::exit(2);
+    });
+
+    let config = ServerWorldConfig {
+        simulation_distance: options.simulation_distance,
+        max_players: 32,
+        allow_noclip: false,
+        chunk_streaming: options.chunk_streaming,
+        chunk_bandwidth: compute::MIB * 2,
+        edit_history_size: compute::MIB * 64,
+        world_directory: Some(options.world_directory.clone()),
+        operators: options.operators.clone(),
+        world_config: WorldConfig {
+            seed: options.seed,
+            max_world_size: USizeVec3::new(1024, 1024, 1024),
+            noise_scale: 0.03,
+        },
+    };
+    let addr = SocketAddr::new(options.bind, options.port);
+    let network = NetworkHandle::bind(addr, compute::MIB * 4);
+    let server = ServerWorld::new(config, network);
+
+    let (interrupt, interrupted) = channel::bounded(1);
+    ctrlc::set_handler(move || {
+        let _ = interrupt.try_send(());
+    })
+    .expect("failed to set the ctrl-c handler");
+
+    // commands typed into the terminal run with every permission
+    let mut console_input = spawn_console_input();
+    let tick_config = TickLoopConfig {
+        tick_rate: options.tick_rate,
+        ..TickLoopConfig::default()
+    };
+    let mut server = ServerThread::spawn(server, tick_config);
+    println!(
+        "serving {} on {addr} at {} ticks per second",
+        options.world_directory.display(),
+        options.tick_rate
+    );
+    // a stop command ends the server thread on its own, checked for between inputs
+    while !server.is_finished() {
+        crossbeam::select! {
+            recv(console_input) -> line => match line {
+                Ok(line) => server.run_command(line.trim_start_matches('/').to_string()),
+                Err(_) => console_input = channel::never(), // no terminal attached
+            },
+            recv(interrupted) -> _ => break,
+            default(Duration::from_millis(250)) => {}
+        }
+    }
+
+    println!("shutting down");
+    server.stop();
+}
@@ -18,3 +18,11 @@ pub fn chunk_to_world_pos(chunk_pos: IVec3) -> Vec3 {
         world_pos_round.z as f32,
     )
 }
+
+pub fn voxel_to_chunk_pos(voxel: IVec3) -> IVec3 {
+    voxel.div_euclid(IVec3::splat(CHUNK_DIM as i32))
+}
+
+pub fn voxel_to_local_pos(voxel: IVec3) -> IVec3 {
+    voxel.rem_euclid(IVec3::splat(CHUNK_DIM as i32))
+}
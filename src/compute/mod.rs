@@ -3,7 +3,9 @@ pub mod bytes;
 mod constants;
 pub mod geo;
 pub mod num;
+pub mod rng;
 pub mod throttler;
+pub mod timestep;
 pub mod utils;
 
 pub use constants::*;
@@ -69,3 +69,46 @@ impl SpatialThrottler {
         self.throttler.set_now(now);
     }
 }
+
+/// refills `rate` tokens per second up to `capacity`, taking may overdraw into debt
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    pub fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    pub fn available(&self) -> f64 {
+        self.tokens
+    }
+
+    /// takes `amount` regardless of what is left, used when the cost is only known afterwards
+    pub fn take(&mut self, amount: f64) {
+        self.tokens -= amount;
+    }
+
+    pub fn try_take(&mut self, amount: f64) -> bool {
+        let can_take = self.tokens >= amount;
+        if can_take {
+            self.tokens -= amount;
+        }
+        can_take
+    }
+}
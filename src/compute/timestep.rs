@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps: u32,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(rate_hz: u32, max_steps: u32) -> Self {
+        debug_assert!(rate_hz > 0 && max_steps > 0);
+        Self {
+            step: Duration::from_secs_f64(1.0 / rate_hz as f64),
+            max_steps,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    /// accumulates `elapsed` and returns how many fixed steps should run now, and whether time
+    /// beyond `max_steps` was dropped so a long stall can't spiral
+    pub fn advance(&mut self, elapsed: Duration) -> (u32, bool) {
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_steps {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        let discarded = self.accumulator >= self.step;
+        if discarded {
+            self.accumulator = Duration::ZERO;
+        }
+        (steps, discarded)
+    }
+
+    #[inline]
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    #[inline]
+    pub fn step_secs(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// fraction of a step left in the accumulator, for interpolating between steps
+    #[inline]
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_time_dropped_past_max_steps() {
+        let mut step = FixedTimestep::new(10, 3);
+        assert_eq!(step.advance(Duration::from_millis(250)), (2, false));
+        // catching up on exactly `max_steps` drops nothing
+        assert_eq!(step.advance(Duration::from_millis(250)), (3, false));
+        assert_eq!(step.advance(Duration::from_secs(1)), (3, true));
+        assert_eq!(step.alpha(), 0.0);
+    }
+}
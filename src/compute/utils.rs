@@ -1,3 +1,4 @@
+use crossbeam::channel;
 use rustc_hash::FxHashMap;
 
 #[inline(always)]
@@ -13,3 +14,19 @@ where
 {
     unsafe { &mut *(mut_ref as *mut T) }
 }
+
+/// lines typed into the terminal, read on their own thread so loops polling them don't block
+pub fn spawn_console_input() -> channel::Receiver<String> {
+    let (sender, receiver) = channel::unbounded();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
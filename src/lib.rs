@@ -0,0 +1,13 @@
+extern crate core;
+
+#[cfg(feature = "client")]
+pub mod app;
+pub mod compute;
+mod macros;
+#[cfg(feature = "client")]
+pub mod renderer;
+#[cfg(feature = "client")]
+pub mod vtypes;
+pub mod world;
+
+use voxer_network;
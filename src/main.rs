@@ -1,24 +1,31 @@
-extern crate core;
-
-mod app;
-pub mod compute;
-mod macros;
-mod renderer;
-mod vtypes;
-mod world;
-
-use crate::compute::geo::AABB;
-use crate::world::{ClientWorldConfig, ServerWorld, ServerWorldConfig, WorldConfig};
 use glam::{IVec2, USizeVec3};
-use voxer_network;
-use vtypes::{CameraController, VObject};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use voxer::compute::geo::AABB;
+use voxer::vtypes::{CameraController, VObject};
+use voxer::world::{
+    ChunkStreamingMode, ClientWorldConfig, LoopbackNetwork, NetworkConditions, NetworkHandle,
+    ServerThread, ServerWorld, ServerWorldConfig, TickLoopConfig, WorldConfig,
+};
+use voxer::{app, compute, vtypes};
 use winit::event_loop::ControlFlow;
 
 fn run_app() {
     const SIMULATION_AND_RENDER_DISTANCE: usize = 24;
 
+    let chunk_streaming = arg_value("--chunk-streaming").map_or(ChunkStreamingMode::Pull, |mode| {
+        ChunkStreamingMode::parse(&mode)
+            .unwrap_or_else(|| panic!("--chunk-streaming: expected push or pull, got {mode}"))
+    });
     let server_config = ServerWorldConfig {
         simulation_distance: SIMULATION_AND_RENDER_DISTANCE,
+        max_players: 32,
+        allow_noclip: true,
+        chunk_streaming,
+        chunk_bandwidth: compute::MIB * 2,
+        edit_history_size: compute::MIB * 64,
+        world_directory: Some(PathBuf::from("saves/world")),
+        operators: vec![IpAddr::from([127, 0, 0, 1])], // the host plays from this machine
         world_config: WorldConfig {
             seed: 0,
             max_world_size: USizeVec3::new(1024, 1024, 1024),
@@ -28,42 +35,93 @@ fn run_app() {
 
     let client_config = ClientWorldConfig {
         render_distance: SIMULATION_AND_RENDER_DISTANCE,
+        player_name: "bill".to_string(),
     };
 
-    let mut server = ServerWorld::new(server_config);
+    // the server and client talk in process unless others should be able to join
+    let open_to_lan = std::env::args().any(|arg| arg == "--lan");
+    let (server_network, client_network) = match open_to_lan {
+        true => (
+            NetworkHandle::bind(SocketAddr::from(([0, 0, 0, 0], 3100)), compute::MIB * 4),
+            NetworkHandle::bind(SocketAddr::from(([0, 0, 0, 0], 0)), compute::MIB * 4),
+        ),
+        false => {
+            let loopback = LoopbackNetwork::default();
+            (
+                NetworkHandle::loopback(&loopback, SocketAddr::from(([127, 0, 0, 1], 3100))),
+                NetworkHandle::loopback(&loopback, SocketAddr::from(([127, 0, 0, 1], 0))),
+            )
+        }
+    };
+    // e.g. --net-sim=latency=80,jitter=20,loss=0.05 to play over a bad connection
+    let net_sim = arg_value("--net-sim")
+        .map(|spec| NetworkConditions::parse(&spec).unwrap_or_else(|e| panic!("--net-sim: {e}")));
+    let (server_network, client_network) = match net_sim {
+        Some(conditions) => (
+            server_network.with_conditions(conditions),
+            client_network.with_conditions(conditions.reseeded(conditions.seed.wrapping_add(1))),
+        ),
+        None => (server_network, client_network),
+    };
+    let server_network = with_capture(server_network, "server");
+    let client_network = with_capture(client_network, "client");
+    let server = ServerWorld::new(server_config, server_network);
     let voxer_engine = vtypes::Voxer::default();
     let scene = vtypes::Scene {
         objects: vec![VObject::Camera(CameraController::with_sensitivity(0.01))],
     };
 
-    server.start_session();
-    let mut app = app::App::new(voxer_engine, server, scene, client_config);
+    let server = ServerThread::spawn(server, TickLoopConfig::default());
+    let mut app = app::App::new(voxer_engine, server, scene, client_config, client_network);
     let event_loop = winit::event_loop::EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
     event_loop.run_app(&mut app).unwrap();
 }
 
+/// `--name=value` from the command line
+fn arg_value(name: &str) -> Option<String> {
+    let prefix = format!("{name}=");
+    std::env::args().find_map(|arg| arg.strip_prefix(&prefix).map(str::to_string))
+}
+
+/// `--record-<side>=<file>` captures the side's traffic, `--replay-<side>=<file>` feeds a capture
+/// back into it in place of the network
+fn with_capture(network: NetworkHandle, side: &str) -> NetworkHandle {
+    let network = match arg_value(&format!("--replay-{side}")) {
+        Some(path) => {
+            NetworkHandle::replay(&path).unwrap_or_else(|e| panic!("--replay-{side}: {e}"))
+        }
+        None => network,
+    };
+    match arg_value(&format!("--record-{side}")) {
+        Some(path) => network
+            .with_recorder(&path)
+            .unwrap_or_else(|e| panic!("--record-{side}: {e}")),
+        None => network,
+    }
+}
+
 fn main() {
     run_app();
     // debug();
 }
 
 fn debug() {
-    use crate::compute;
-    use crate::compute::geo::IVec3Iter;
-    use crate::renderer::gpu::{
-        GPUVoxelChunk, GPUVoxelChunkAdjContent, GPUVoxelChunkContent, GPUVoxelFaceData,
-    };
     use glam::{IVec3, Vec3};
     use rustc_hash::{FxHashMap, FxHashSet};
     use std::hint::black_box;
     use std::time::Duration;
     use std::time::Instant;
+    use voxer::compute;
+    use voxer::compute::geo::IVec3Iter;
+    use voxer::renderer::gpu::{
+        GPUVoxelChunk, GPUVoxelChunkAdjContent, GPUVoxelChunkContent, GPUVoxelFaceData,
+    };
 
     let pos = black_box(IVec3::new(100, 100, 100));
     let rad = black_box(128isize);
 
-    dbg!(size_of::<crate::renderer::gpu::GPUVoxelChunkHeader>());
+    dbg!(size_of::<voxer::renderer::gpu::GPUVoxelChunkHeader>());
 
     let mut c = 0;
     let mut c2 = 0;
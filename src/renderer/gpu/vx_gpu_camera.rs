@@ -4,6 +4,7 @@ use voxer_macros::ShaderType;
 use winit::dpi::PhysicalSize;
 use crate::compute::geo::{Frustum, Plane};
 use crate::vtypes::Camera;
+use crate::world::time::Sky;
 
 #[repr(C, align(16))]
 #[derive(ShaderType, Copy, Clone, Debug, Pod, Zeroable)]
@@ -18,10 +19,16 @@ pub struct VxGPUCamera {
     window_size: UVec2,
     culling_dist: u32,
     _padding: u32,
+    sun: Vec4, // xyz towards the sun, w ambient light
 }
 
 impl VxGPUCamera {
-    pub fn new(main_camera: &Camera, culling_dist: u32, window_size: PhysicalSize<u32>) -> Self {
+    pub fn new(
+        main_camera: &Camera,
+        culling_dist: u32,
+        window_size: PhysicalSize<u32>,
+        sky: &Sky,
+    ) -> Self {
         let window_size = UVec2::new(window_size.width, window_size.height);
 
         let view_origin = main_camera.transform.position.extend(1.0); // for alignment
@@ -43,6 +50,7 @@ impl VxGPUCamera {
             culling_vf,
             window_size,
             _padding: 0,
+            sun: sky.sun_direction.extend(sky.ambient_light),
         }
     }
 }
\ No newline at end of file
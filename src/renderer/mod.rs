@@ -158,6 +158,7 @@ impl<'window> Renderer<'window> {
         encoder: &'e mut wgpu::CommandEncoder,
         label: &str,
         frame_view: &TextureView,
+        clear_color: wgpu::Color,
     ) -> wgpu::RenderPass<'e> {
         encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some(label),
@@ -166,12 +167,7 @@ impl<'window> Renderer<'window> {
                 depth_slice: None,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.0,
-                        g: 0.1,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(clear_color),
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -8,7 +8,8 @@ var atlas_sampler: sampler;
 fn fs_main(
     @location(0) tex_coords: vec2<f32>,
     @location(1) ao: f32,
+    @location(2) light: f32,
 ) -> @location(0) vec4<f32> {
     let sampled_tex = textureSample(atlas_texture, atlas_sampler, tex_coords);
-    return vec4(sampled_tex.rgb * ao, sampled_tex.a);
+    return vec4(sampled_tex.rgb * ao * light, sampled_tex.a);
 }
@@ -8,6 +8,7 @@ struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) ao: f32,
+    @location(2) light: f32,
 };
 
 @vertex
@@ -32,5 +33,9 @@ fn vs_main(
     out.tex_coords = TEX_COORDS[vertex_index];
     out.ao = occlusion_count_to_ao(face_ao[vertex_index]);
 
+    let ambient = vx_camera.sun.w;
+    let sun_light = max(dot(FACE_NORMALS[face_voxel.face_id], vx_camera.sun.xyz), 0.0);
+    out.light = ambient + (1.0 - ambient) * sun_light * smoothstep(-0.1, 0.25, vx_camera.sun.y);
+
     return out;
 }
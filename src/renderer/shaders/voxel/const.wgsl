@@ -8,6 +8,15 @@ const TEX_COORDS = array<vec2<f32>, 4>(
     vec2<f32>(0.5, 0.0 + TILE_DIM),
 );
 
+const FACE_NORMALS = array<vec3<f32>, 6>(
+    vec3<f32>(-1.0, 0.0, 0.0),
+    vec3<f32>(1.0, 0.0, 0.0),
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, -1.0, 0.0),
+    vec3<f32>(0.0, 0.0, 1.0),
+    vec3<f32>(0.0, 0.0, -1.0),
+);
+
 const QUAD_INDICES = array<u32, 6>(0, 1, 2, 0, 2, 3);
 
 const QUAD_VERTICES = array<array<vec3<f32>, 4>, 6>(
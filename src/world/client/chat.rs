@@ -0,0 +1,40 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Instant;
+
+const SCROLLBACK_LINES: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct ChatLine {
+    pub sender: Option<String>, // None for system messages
+    pub text: String,
+    pub received: Instant,
+}
+
+impl fmt::Display for ChatLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.sender {
+            Some(sender) => write!(f, "<{}> {}", sender, self.text),
+            None => write!(f, "* {}", self.text),
+        }
+    }
+}
+
+/// the latest chat and system messages, oldest first, for the ui to show
+#[derive(Default)]
+pub struct ChatLog {
+    lines: VecDeque<ChatLine>,
+}
+
+impl ChatLog {
+    pub fn push(&mut self, line: ChatLine) {
+        if self.lines.len() == SCROLLBACK_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &ChatLine> {
+        self.lines.iter()
+    }
+}
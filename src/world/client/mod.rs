@@ -1,29 +1,36 @@
+mod chat;
 mod network;
+mod remote_players;
 mod session;
 
 use crate::app::app_renderer::AppRenderer;
-use crate::compute::MIB;
 use crate::compute::geo::{Frustum, Plane, world_to_chunk_pos};
 use crate::vtypes::Camera;
 use crate::world::CHUNK_DIM;
+use crate::world::client::chat::ChatLine;
 use crate::world::client::network::ClientWorldNetwork;
-use crate::world::client::session::ClientWorldSession;
+use crate::world::client::session::{ClientWorldSession, PositionCorrection};
 use crate::world::network::{
-    MAX_CHUNKS_PER_BATCH, MsgChunkData, MsgChunkDataEmpty, NetworkHandle, ServerMessage,
-    ServerMessageTag,
+    ChatError, ChatKind, ChunkStreamingMode, MAX_CHUNKS_PER_BATCH, MsgChat, MsgChatRequest,
+    MsgChunkData, MsgChunkDataEmpty, MsgConnect, MsgConnectDeny, MsgEntityDespawn, MsgEntitySpawn,
+    MsgEntityUpdate, MsgPlayerJoin, MsgPlayerLeave, MsgPlayerSnapshot, MsgSetPosition,
+    MsgWorldTime, NetworkHandle, PROTOCOL_VERSION, ServerMessage, ServerMessageTag,
+    SetPositionKind, block_deltas, chunk_codec, encoded_chunk_payload, handshake_protocol_version,
 };
 use crate::world::server::chunk::VoxelChunk;
 use crate::world::session::{PlayerLocation, PlayerSession};
-use glam::{IVec3, Vec3};
+use glam::{IVec3, Quat, Vec3};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use voxer_network::NetworkDeserializable;
 use wgpu::CommandEncoder;
 use winit::window::Window;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct ClientWorldConfig {
     pub render_distance: usize,
+    pub player_name: String,
 }
 
 pub struct ClientWorld<'window> {
@@ -32,49 +39,89 @@ pub struct ClientWorld<'window> {
     pub renderer: AppRenderer<'window>,
 
     player: PlayerSession,
+    noclip: bool,
 
     network: ClientWorldNetwork,
     temp_server_addr: SocketAddr,
 }
 
 impl ClientWorld<'_> {
-    pub fn new(window: Arc<Window>, config: ClientWorldConfig) -> Self {
+    pub fn new(window: Arc<Window>, config: ClientWorldConfig, network: NetworkHandle) -> Self {
         let player = PlayerSession {
-            id: 0,
-            name: "bill".to_string(),
+            id: 0, // assigned by the server on connect
+            name: config.player_name.clone(),
             location: PlayerLocation {
                 world: 0,
                 position: Vec3::ZERO,
+                rotation: Quat::IDENTITY,
             },
         };
-        let socket_addr = SocketAddr::from(([0, 0, 0, 0], 0));
         let temp_server_addr = SocketAddr::from(([127, 0, 0, 1], 3100));
 
-        let network_handle = NetworkHandle::bind(socket_addr, MIB * 4);
-        let mut network = ClientWorldNetwork::new(network_handle, config.render_distance as u32);
+        let mut network = ClientWorldNetwork::new(network, config.render_distance as u32);
         network.set_server_addr(temp_server_addr); // fixme temp
         Self {
-            config,
-            session: ClientWorldSession::new(config, world_to_chunk_pos(player.location.position)),
+            session: ClientWorldSession::new(
+                config.clone(),
+                world_to_chunk_pos(player.location.position),
+            ),
             renderer: AppRenderer::new(window, config.render_distance),
+            config,
             network,
             player,
+            noclip: false,
             temp_server_addr,
         }
     }
 
     pub(crate) fn temp_set_camera(&mut self, camera: Camera) {
-        self.player.location.position = camera.transform.position;
+        self.player.location.rotation = camera.transform.rotation;
         self.session.camera = camera;
     }
 
-    pub(crate) fn temp_send_req_conn(&self) {
-        self.network.send_connection_request(self.temp_server_addr);
+    pub(crate) fn set_player_position(&mut self, feet_position: Vec3, noclip: bool) {
+        self.player.location.position = feet_position;
+        self.noclip = noclip;
     }
 
-    pub(crate) fn temp_send_player_position(&self) {
+    pub(crate) fn temp_send_req_conn(&mut self) {
         self.network
-            .send_player_position(self.player.location.position);
+            .send_connection_request(self.temp_server_addr, &self.player.name);
+    }
+
+    pub(crate) fn disconnect(&mut self) {
+        self.network.disconnect();
+    }
+
+    pub(crate) fn flush_capture(&self) {
+        self.network.flush_capture();
+    }
+
+    pub(crate) fn rtt(&self) -> Option<Duration> {
+        self.network.rtt()
+    }
+
+    /// position the server placed the player at, if it did since the last call
+    pub(crate) fn take_correction(&mut self) -> Option<PositionCorrection> {
+        self.session.correction.take()
+    }
+
+    /// sends `text` to everyone, if the server accepts it it comes back as chat
+    pub(crate) fn send_chat(&self, text: &str) -> Result<(), ChatError> {
+        let msg = MsgChatRequest::new(text)?;
+        if self.network.is_connected() {
+            self.network.send_chat(msg);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn temp_send_player_position(&self) {
+        self.network.send_player_position(
+            self.player.location.position,
+            self.player.location.rotation,
+            self.session.teleport_id,
+            self.noclip,
+        );
     }
 
     fn request_interest_chunks(&mut self, origin: IVec3) {
@@ -86,12 +133,14 @@ impl ClientWorld<'_> {
         self.network.request_chunk_batch();
     }
 
-    pub fn tick(&mut self, encoder: &mut CommandEncoder) {
+    pub fn tick(&mut self, encoder: &mut CommandEncoder, dt: f32) {
         self.network.receive_messages(|msg| {
-            Self::handle_network_message(&mut self.session, msg);
+            Self::handle_network_message(&mut self.session, &mut self.player, msg);
         });
+        self.network.poll_keepalive();
+        self.network.resend_unacked();
         let player_ch_pos = world_to_chunk_pos(self.player.location.position);
-        self.session.tick(player_ch_pos);
+        self.session.tick(player_ch_pos, dt);
 
         // render fixme move logic to renderer
         let safe_voxel_rdist = ((self.config.render_distance - 1) * CHUNK_DIM) as f32;
@@ -103,26 +152,141 @@ impl ClientWorld<'_> {
         self.renderer
             .update_chunk_meshes(encoder, mesh_chunks, player_ch_pos, &view_planes);
 
-        self.request_interest_chunks(player_ch_pos);
+        if !self.network.is_connected() {
+            return;
+        }
+        match self.session.chunk_streaming {
+            ChunkStreamingMode::Push => {
+                let acks = std::mem::take(&mut self.session.chunk_acks);
+                self.network.send_chunk_acks(&acks);
+            }
+            _ => self.request_interest_chunks(player_ch_pos),
+        }
     }
 
-    fn handle_network_message(session: &mut ClientWorldSession, message: ServerMessage) {
+    fn handle_network_message(
+        session: &mut ClientWorldSession,
+        player: &mut PlayerSession,
+        message: ServerMessage,
+    ) {
         match message.tag {
             ServerMessageTag::ChunkData => {
                 let chunk_data_msg = MsgChunkData::deserialize(message.message.data);
                 let chunk = VoxelChunk::from(chunk_data_msg);
-                session.add_new_chunk(chunk);
+                session.receive_chunk(chunk);
             }
             ServerMessageTag::ChunkDataEmpty => {
                 let chunk_data_msg = MsgChunkDataEmpty::deserialize(message.message.data);
                 let chunk = VoxelChunk::from(chunk_data_msg);
-                session.add_new_chunk(chunk);
+                session.receive_chunk(chunk);
+            }
+            ServerMessageTag::ChunkDataEncoded => {
+                let Some((position, payload)) = encoded_chunk_payload(&message.message.data) else {
+                    return;
+                };
+                match chunk_codec::decode(payload) {
+                    Ok((blocks, voxel_count)) => {
+                        session.receive_chunk(VoxelChunk::new(position, blocks, voxel_count));
+                    }
+                    Err(e) => println!("failed to decode chunk {position}: {:?}", e),
+                }
+            }
+            ServerMessageTag::UpdateChunks => {
+                let Some(deltas) = block_deltas(&message.message.data) else {
+                    return;
+                };
+                session.apply_block_deltas(&deltas);
+            }
+            ServerMessageTag::EntitySpawn => {
+                let spawn_msg = MsgEntitySpawn::deserialize(message.message.data);
+                if let Some(entity) = spawn_msg.to_entity() {
+                    session.entities.insert(entity);
+                }
+            }
+            ServerMessageTag::EntityUpdate => {
+                let update_msg = MsgEntityUpdate::deserialize(message.message.data);
+                if let Some(entity) = session.entities.get_mut(update_msg.id) {
+                    update_msg.apply(entity);
+                }
+            }
+            ServerMessageTag::EntityDespawn => {
+                let despawn_msg = MsgEntityDespawn::deserialize(message.message.data);
+                session.entities.remove(despawn_msg.id);
+            }
+            ServerMessageTag::PlayerJoin => {
+                let join_msg = MsgPlayerJoin::deserialize(message.message.data);
+                let name = join_msg.name().unwrap_or("?").to_string();
+                session.remote_players.join(join_msg.player_id, name);
+            }
+            ServerMessageTag::PlayerLeave => {
+                let leave_msg = MsgPlayerLeave::deserialize(message.message.data);
+                session.remote_players.leave(leave_msg.player_id);
+            }
+            ServerMessageTag::PlayerSnapshot => {
+                let snapshot_msg = MsgPlayerSnapshot::deserialize(message.message.data);
+                session
+                    .remote_players
+                    .receive_snapshot(&snapshot_msg, Instant::now());
+            }
+            ServerMessageTag::Chat => {
+                let chat_msg = MsgChat::deserialize(message.message.data);
+                let Some(text) = chat_msg.text() else {
+                    return;
+                };
+                let sender = match chat_msg.kind() {
+                    Some(ChatKind::Player) => chat_msg.sender().map(str::to_string),
+                    _ => None,
+                };
+                let line = ChatLine {
+                    sender,
+                    text: text.to_string(),
+                    received: Instant::now(),
+                };
+                println!("{line}");
+                session.chat.push(line);
+            }
+            ServerMessageTag::WorldTime => {
+                let time_msg = MsgWorldTime::deserialize(message.message.data);
+                session.clock.time = time_msg.world_time();
+            }
+            ServerMessageTag::Connect => {
+                let connect_msg = MsgConnect::deserialize(message.message.data);
+                player.id = connect_msg.player_id as usize;
+                player.location.world = connect_msg.world as usize;
+                // whoever is there already is announced again after connecting
+                session.remote_players.clear();
+                session.chunk_streaming = connect_msg
+                    .chunk_streaming()
+                    .unwrap_or(ChunkStreamingMode::Pull);
+                session.correction = Some(PositionCorrection {
+                    position: connect_msg.spawn,
+                    snap: true,
+                });
+            }
+            ServerMessageTag::ConnectDeny => {
+                let data = message.message.data;
+                let server_version = handshake_protocol_version(&data).unwrap();
+                if server_version != PROTOCOL_VERSION || data.len() != size_of::<MsgConnectDeny>() {
+                    println!(
+                        "connection denied by a server on protocol {}, ours is {}",
+                        server_version, PROTOCOL_VERSION,
+                    );
+                    return;
+                }
+                let deny_msg = MsgConnectDeny::deserialize(data);
+                println!("connection denied: {:?}", deny_msg.reason());
             }
             ServerMessageTag::SetPosition => {
-                todo!()
+                let position_msg = MsgSetPosition::deserialize(message.message.data);
+                session.teleport_id = position_msg.teleport_id;
+                session.correction = Some(PositionCorrection {
+                    position: position_msg.position,
+                    snap: position_msg.kind() != Some(SetPositionKind::Correction),
+                });
             }
-            ServerMessageTag::Ping => unimplemented!(),
-            _ => unimplemented!(),
+            // keepalive and disconnects are handled by the network, anything else is not sent
+            // to clients and was dropped on receiving
+            _ => {}
         }
     }
 }
@@ -1,8 +1,11 @@
 use crate::compute::throttler::SpatialThrottler;
 use crate::world::network::{
-    MsgChunkDataRequest, MsgConnectRequest, MsgSetPositionRequest, NetworkHandle, ServerMessage,
+    DisconnectReason, Endpoint, Keepalive, MAX_CHUNKS_PER_BATCH, MsgChatRequest, MsgChunkDataAck,
+    MsgChunkDataRequest, MsgConnectRequest, MsgDisconnect, MsgDisconnectRequest, MsgPing, MsgPong,
+    MsgSetPositionRequest, NetworkHandle, ServerMessage, ServerMessageTag,
 };
-use glam::{IVec3, UVec3, Vec3};
+use voxer_network::NetworkDeserializable;
+use glam::{IVec3, Quat, UVec3, Vec3};
 use std::net::SocketAddr;
 use std::time::Instant;
 
@@ -12,11 +15,13 @@ pub struct ClientWorldNetwork {
     chunk_request_batch: Vec<IVec3>,
     message_buffer: Vec<ServerMessage>,
     server_addr: Option<SocketAddr>,
+    keepalive: Keepalive,
+    disconnect_reason: Option<DisconnectReason>,
 }
 
 impl ClientWorldNetwork {
     pub(crate) fn new(mut network_handle: NetworkHandle, render_distance: u32) -> Self {
-        network_handle.listen();
+        network_handle.listen(Endpoint::Client);
         Self {
             network_handle,
             chunk_request_throttler: SpatialThrottler::new(
@@ -26,18 +31,88 @@ impl ClientWorldNetwork {
             chunk_request_batch: Vec::new(), // fixme capacity
             message_buffer: Vec::new(),      // fixme capacity
             server_addr: None,
+            keepalive: Keepalive::new(Instant::now()),
+            disconnect_reason: None,
         }
     }
 
+    /// keepalive and disconnect messages are handled here, everything else is passed to `f`
     pub(crate) fn receive_messages<F: FnMut(ServerMessage)>(&mut self, mut f: F) {
         let message_buffer = &mut self.message_buffer;
         message_buffer.clear();
         self.network_handle.take_messages_out(64, message_buffer);
+        let now = Instant::now();
         for msg in message_buffer.drain(..) {
-            f(msg);
+            if self.server_addr != Some(msg.message.src) {
+                continue;
+            }
+            self.keepalive.on_received(now);
+            match msg.tag {
+                ServerMessageTag::Ping => {
+                    let ping = MsgPing::deserialize(msg.message.data);
+                    let pong = MsgPong { id: ping.id };
+                    self.network_handle.send(pong, &msg.message.src).unwrap();
+                }
+                ServerMessageTag::Pong => {
+                    let pong = MsgPong::deserialize(msg.message.data);
+                    self.keepalive.on_pong(&pong, now);
+                }
+                ServerMessageTag::Disconnect => {
+                    let disconnect = MsgDisconnect::deserialize(msg.message.data);
+                    let reason = disconnect.reason().unwrap_or(DisconnectReason::Kicked);
+                    println!("disconnected by server: {:?}", reason);
+                    self.disconnect_reason = Some(reason);
+                }
+                _ => f(msg),
+            }
         }
     }
 
+    /// pings the server when due and notices when it stopped answering
+    pub(crate) fn poll_keepalive(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+        let now = Instant::now();
+        if self.keepalive.is_timed_out(now) {
+            println!("connection to server timed out");
+            self.disconnect_reason = Some(DisconnectReason::Timeout);
+            return;
+        }
+        if let Some(ping) = self.keepalive.poll_ping(now) {
+            self.network_handle.send(ping, self.server_addr()).unwrap();
+        }
+    }
+
+    pub(crate) fn resend_unacked(&mut self) {
+        let broken = self.network_handle.resend_unacked();
+        if self.is_connected() && broken.contains(self.server_addr()) {
+            println!("connection to server broke, reliable messages went unacked");
+            self.disconnect_reason = Some(DisconnectReason::Timeout);
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.server_addr.is_some() && self.disconnect_reason.is_none()
+    }
+
+    pub fn rtt(&self) -> Option<std::time::Duration> {
+        self.keepalive.rtt()
+    }
+
+    pub fn disconnect(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+        let msg = MsgDisconnectRequest { byte: 0 };
+        self.network_handle.send(msg, self.server_addr()).unwrap();
+        self.disconnect_reason = Some(DisconnectReason::Quit);
+    }
+
+    pub(crate) fn flush_capture(&self) {
+        self.network_handle.flush_capture();
+    }
+
     fn server_addr(&self) -> &SocketAddr {
         self.server_addr.as_ref().unwrap()
     }
@@ -57,25 +132,38 @@ impl ClientWorldNetwork {
         if self.chunk_request_batch.is_empty() {
             return;
         }
-        let chunk_data_request = MsgChunkDataRequest::with_positions(&self.chunk_request_batch);
-        let msg = Box::new(chunk_data_request);
-        self.network_handle
-            .send_to(msg, self.server_addr())
-            .unwrap();
+        let msg = MsgChunkDataRequest::with_positions(&self.chunk_request_batch);
+        self.network_handle.send(msg, self.server_addr()).unwrap();
+    }
+
+    pub(crate) fn send_chunk_acks(&self, positions: &[IVec3]) {
+        for batch in positions.chunks(MAX_CHUNKS_PER_BATCH) {
+            let msg = MsgChunkDataAck::with_positions(batch);
+            self.network_handle.send(msg, self.server_addr()).unwrap();
+        }
+    }
+
+    pub fn send_player_position(
+        &self,
+        position: Vec3,
+        rotation: Quat,
+        teleport_id: u32,
+        noclip: bool,
+    ) {
+        let msg = MsgSetPositionRequest::new(position, rotation, teleport_id, noclip);
+        self.network_handle.send(msg, self.server_addr()).unwrap();
     }
 
-    pub fn send_player_position(&self, position: Vec3) {
-        let set_position_request = MsgSetPositionRequest { position };
-        let msg = Box::new(set_position_request);
-        self.network_handle
-            .send_to(msg, self.server_addr())
-            .unwrap();
+    pub(crate) fn send_chat(&self, msg: MsgChatRequest) {
+        self.network_handle.send(msg, self.server_addr()).unwrap();
     }
 
-    pub fn send_connection_request(&self, server_addr: SocketAddr) {
-        let connection_request = MsgConnectRequest { byte: 62 };
-        let msg = Box::new(connection_request);
-        self.network_handle.send_to(msg, &server_addr).unwrap();
+    pub fn send_connection_request(&mut self, server_addr: SocketAddr, player_name: &str) {
+        self.keepalive = Keepalive::new(Instant::now());
+        self.disconnect_reason = None;
+        self.network_handle.reset_peer(server_addr);
+        let msg = MsgConnectRequest::new(player_name);
+        self.network_handle.send(msg, &server_addr).unwrap();
     }
 
     pub fn set_server_addr(&mut self, server_addr: SocketAddr) {
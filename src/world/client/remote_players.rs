@@ -0,0 +1,129 @@
+use crate::world::network::MsgPlayerSnapshot;
+use glam::{Quat, Vec3};
+use rustc_hash::FxHashMap;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// shown this far behind the latest snapshot, so there is usually a later one to move towards
+const INTERPOLATION_DELAY: u32 = 100; // ms, two snapshot intervals
+const HIDE_AFTER: u32 = 500; // ms without a snapshot, they are out of range or gone
+const MAX_SAMPLES: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+struct PlayerSample {
+    time: u32, // server milliseconds
+    position: Vec3,
+    rotation: Quat,
+}
+
+#[derive(Debug)]
+pub struct RemotePlayer {
+    pub name: String,
+    pub position: Vec3, // feet position, interpolated
+    pub rotation: Quat,
+    pub visible: bool, // false until the first snapshot and while out of range
+    samples: VecDeque<PlayerSample>,
+}
+
+impl RemotePlayer {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            visible: false,
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+        }
+    }
+
+    fn push(&mut self, sample: PlayerSample) {
+        // snapshots can arrive out of order, late ones are of no use
+        if self
+            .samples
+            .back()
+            .is_some_and(|last| last.time >= sample.time)
+        {
+            return;
+        }
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn interpolate(&mut self, render_time: u32) {
+        while self.samples.len() > 2 && self.samples[1].time <= render_time {
+            self.samples.pop_front();
+        }
+        let Some(&latest) = self.samples.back() else {
+            return;
+        };
+        self.visible = render_time.saturating_sub(latest.time) < HIDE_AFTER;
+        let from = self.samples[0];
+        let to = match self.samples.get(1) {
+            Some(&to) if render_time > from.time => to,
+            _ => from,
+        };
+        // held at the latest sample when snapshots stop coming, rather than guessing ahead
+        let span = to.time.saturating_sub(from.time).max(1) as f32;
+        let t = (render_time.saturating_sub(from.time) as f32 / span).min(1.0);
+        self.position = from.position.lerp(to.position, t);
+        self.rotation = from.rotation.slerp(to.rotation, t);
+    }
+}
+
+/// the other players the server told us about, by id
+#[derive(Default)]
+pub struct RemotePlayers {
+    pub players: FxHashMap<u32, RemotePlayer>,
+    latest_snapshot: Option<(u32, Instant)>, // server time of the newest snapshot and when it came
+}
+
+impl RemotePlayers {
+    pub fn join(&mut self, player_id: u32, name: String) {
+        self.players.insert(player_id, RemotePlayer::new(name));
+    }
+
+    pub fn leave(&mut self, player_id: u32) -> Option<RemotePlayer> {
+        self.players.remove(&player_id)
+    }
+
+    pub fn clear(&mut self) {
+        self.players.clear();
+        self.latest_snapshot = None;
+    }
+
+    /// players we weren't told joined yet are skipped, the join is reliable and on its way
+    pub fn receive_snapshot(&mut self, snapshot: &MsgPlayerSnapshot, now: Instant) {
+        if self
+            .latest_snapshot
+            .is_none_or(|(time, _)| snapshot.time > time)
+        {
+            self.latest_snapshot = Some((snapshot.time, now));
+        }
+        for state in snapshot.states() {
+            let Some(player) = self.players.get_mut(&state.player_id) else {
+                continue;
+            };
+            player.push(PlayerSample {
+                time: snapshot.time,
+                position: state.position,
+                rotation: Quat::from_array(state.rotation),
+            });
+        }
+    }
+
+    /// moves every player to where they were `INTERPOLATION_DELAY` before the server's
+    /// current time, as far as we can tell it
+    pub fn interpolate(&mut self, now: Instant) {
+        let Some((time, received)) = self.latest_snapshot else {
+            return;
+        };
+        let elapsed = now.duration_since(received).min(Duration::from_secs(60));
+        let server_time = time.saturating_add(elapsed.as_millis() as u32);
+        let render_time = server_time.saturating_sub(INTERPOLATION_DELAY);
+        for player in self.players.values_mut() {
+            player.interpolate(render_time);
+        }
+    }
+}
@@ -1,14 +1,34 @@
 use crate::compute::geo::{Sphere, SpherePointsRange, ivec3_with_adjacent_positions};
 use crate::compute::utils::fxmap_with_capacity;
 use crate::vtypes::Camera;
-use crate::world::ClientWorldConfig;
+use crate::world::client::chat::ChatLog;
+use crate::world::client::remote_players::RemotePlayers;
+use crate::world::{CHUNK_DIM, CHUNK_VOLUME, ClientWorldConfig};
+use crate::world::network::{BlockDelta, ChunkStreamingMode};
+use crate::world::entity::EntityStore;
+use crate::world::time::WorldClock;
+use std::time::{Duration, Instant};
 use crate::world::server::chunk::VoxelChunk;
-use glam::IVec3;
+use glam::{IVec3, Vec3};
 use rustc_hash::{FxHashMap, FxHashSet};
 
+#[derive(Debug, Clone, Copy)]
+pub struct PositionCorrection {
+    pub position: Vec3, // feet position
+    pub snap: bool,     // teleports are never smoothed
+}
+
 pub struct ClientWorldSession {
     pub chunks: FxHashMap<IVec3, VoxelChunk>,
     pub camera: Camera,
+    pub entities: EntityStore,
+    pub remote_players: RemotePlayers,
+    pub chat: ChatLog,
+    pub clock: WorldClock,
+    pub correction: Option<PositionCorrection>, // set by the server, consumed by the player controller
+    pub teleport_id: u32, // of the latest server placement, echoed back with our position
+    pub chunk_streaming: ChunkStreamingMode,
+    pub chunk_acks: Vec<IVec3>, // received pushed chunks not acknowledged yet
     config: ClientWorldConfig,
     chunk_drop_dist: i32,
     chunk_gc_batch: Vec<IVec3>,
@@ -18,26 +38,68 @@ pub struct ClientWorldSession {
 
 impl ClientWorldSession {
     pub fn new(config: ClientWorldConfig, start_position: IVec3) -> Self {
+        let render_distance = config.render_distance;
         Self {
-            chunks: fxmap_with_capacity((config.render_distance * 2).pow(3)),
+            chunks: fxmap_with_capacity((render_distance * 2).pow(3)),
             camera: Camera::default(),
+            entities: EntityStore::default(),
+            remote_players: RemotePlayers::default(),
+            chat: ChatLog::default(),
+            clock: WorldClock::default(),
+            correction: None,
+            teleport_id: 0,
+            chunk_streaming: ChunkStreamingMode::Pull,
+            chunk_acks: Vec::new(),
             config,
-            chunk_drop_dist: (config.render_distance as i32).pow(2) + 1,
+            chunk_drop_dist: (render_distance as i32).pow(2) + 1,
             chunk_gc_batch: Vec::new(),
             chunk_interest_positions: Sphere::discrete_points(
                 start_position,
-                config.render_distance as u32 - 1,
+                render_distance as u32 - 1,
             ), // fixme
             chunk_meshing_batch: FxHashSet::default(),
         }
     }
 
+    /// adds a chunk sent by the server, remembering to ack it if it was pushed
+    pub fn receive_chunk(&mut self, chunk: VoxelChunk) {
+        if self.chunk_streaming == ChunkStreamingMode::Push {
+            self.chunk_acks.push(chunk.position);
+        }
+        self.add_new_chunk(chunk);
+    }
+
     pub fn add_new_chunk(&mut self, chunk: VoxelChunk) {
         self.chunk_meshing_batch
             .extend(ivec3_with_adjacent_positions(chunk.position));
         self.chunks.insert(chunk.position, chunk);
     }
 
+    /// applies changed blocks to loaded chunks, remeshing them and any neighbour sharing a changed face
+    pub fn apply_block_deltas(&mut self, deltas: &[BlockDelta]) {
+        let max = CHUNK_DIM as i32 - 1;
+        for delta in deltas {
+            if delta.index as usize >= CHUNK_VOLUME {
+                continue; // outside the chunk, sent by a broken or hostile server
+            }
+            let Some(chunk) = self.chunks.get_mut(&delta.chunk) else {
+                continue; // dropped since, we'll get it whole if we request it again
+            };
+            let local = VoxelChunk::index_local(delta.index);
+            chunk.set_block(local, delta.block);
+            self.chunk_meshing_batch.insert(delta.chunk);
+            for axis in 0..3 {
+                let mut offset = IVec3::ZERO;
+                match local[axis] {
+                    0 => offset[axis] = -1,
+                    l if l == max => offset[axis] = 1,
+                    _ => continue,
+                }
+                self.chunk_meshing_batch.insert(delta.chunk + offset);
+            }
+        }
+    }
+
     pub fn chunk_exists(&self, chunk_position: &IVec3) -> bool {
         self.chunks.contains_key(chunk_position)
     }
@@ -78,7 +140,11 @@ impl ClientWorldSession {
             .filter_map(|p| self.chunks.get(&p))
     }
 
-    pub fn tick(&mut self, camera_origin: IVec3) {
+    pub fn tick(&mut self, camera_origin: IVec3, dt: f32) {
         self.chunk_gc_pass(camera_origin);
+        self.entities.tick(dt);
+        self.entities.clear_changes(); // only servers replicate what changed
+        self.remote_players.interpolate(Instant::now());
+        self.clock.tick(Duration::from_secs_f32(dt));
     }
 }
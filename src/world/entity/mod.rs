@@ -0,0 +1,85 @@
+mod store;
+
+use crate::impl_try_from_uint;
+use crate::world::server::block::VoxelBlock;
+use bytemuck::{Pod, Zeroable};
+use glam::{Quat, Vec3};
+pub use store::EntityStore;
+
+pub const ENTITY_DATA_SIZE: usize = 8;
+
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Pod, Zeroable)]
+pub struct EntityId(pub u32);
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    Marker,
+    Item,
+    FallingBlock,
+    __Count,
+}
+impl_try_from_uint!(u8 => EntityKind);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntityData {
+    Marker,
+    Item { block: VoxelBlock, count: u16 },
+    FallingBlock { block: VoxelBlock },
+}
+
+impl EntityData {
+    pub fn kind(&self) -> EntityKind {
+        match self {
+            EntityData::Marker => EntityKind::Marker,
+            EntityData::Item { .. } => EntityKind::Item,
+            EntityData::FallingBlock { .. } => EntityKind::FallingBlock,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENTITY_DATA_SIZE] {
+        let mut bytes = [0u8; ENTITY_DATA_SIZE];
+        match self {
+            EntityData::Marker => {}
+            EntityData::Item { block, count } => {
+                bytes[0..2].copy_from_slice(&block.value.to_le_bytes());
+                bytes[2..4].copy_from_slice(&count.to_le_bytes());
+            }
+            EntityData::FallingBlock { block } => {
+                bytes[0..2].copy_from_slice(&block.value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(kind: EntityKind, bytes: &[u8; ENTITY_DATA_SIZE]) -> Option<Self> {
+        let block = VoxelBlock {
+            value: u16::from_le_bytes([bytes[0], bytes[1]]),
+        };
+        match kind {
+            EntityKind::Marker => Some(EntityData::Marker),
+            EntityKind::Item => Some(EntityData::Item {
+                block,
+                count: u16::from_le_bytes([bytes[2], bytes[3]]),
+            }),
+            EntityKind::FallingBlock => Some(EntityData::FallingBlock { block }),
+            EntityKind::__Count => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entity {
+    pub id: EntityId,
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub velocity: Vec3,
+    pub data: EntityData,
+}
+
+impl Entity {
+    pub fn kind(&self) -> EntityKind {
+        self.data.kind()
+    }
+}
@@ -0,0 +1,87 @@
+use crate::world::entity::{Entity, EntityData, EntityId};
+use glam::{Quat, Vec3};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+#[derive(Default)]
+pub struct EntityStore {
+    entities: FxHashMap<EntityId, Entity>,
+    next_id: u32,
+    dirty: FxHashSet<EntityId>,
+    despawned: Vec<EntityId>,
+}
+
+impl EntityStore {
+    pub fn spawn(&mut self, data: EntityData, position: Vec3) -> EntityId {
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+        let entity = Entity {
+            id,
+            position,
+            rotation: Quat::IDENTITY,
+            velocity: Vec3::ZERO,
+            data,
+        };
+        self.entities.insert(id, entity);
+        id
+    }
+
+    /// inserts an entity with an id assigned elsewhere, e.g. replicated from a server
+    pub fn insert(&mut self, entity: Entity) {
+        self.entities.insert(entity.id, entity);
+    }
+
+    /// removes an entity without recording it for replication
+    pub fn remove(&mut self, id: EntityId) -> Option<Entity> {
+        self.dirty.remove(&id);
+        self.entities.remove(&id)
+    }
+
+    pub fn despawn(&mut self, id: EntityId) -> Option<Entity> {
+        let entity = self.entities.remove(&id)?;
+        self.dirty.remove(&id);
+        self.despawned.push(id);
+        Some(entity)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    /// marks the entity as changed so it is replicated on the next update
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        let entity = self.entities.get_mut(&id)?;
+        self.dirty.insert(id);
+        Some(entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_dirty(&self, id: EntityId) -> bool {
+        self.dirty.contains(&id)
+    }
+
+    pub fn despawned(&self) -> &[EntityId] {
+        &self.despawned
+    }
+
+    /// forgets changes after they have been replicated
+    pub fn clear_changes(&mut self) {
+        self.dirty.clear();
+        self.despawned.clear();
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        for entity in self.entities.values_mut() {
+            if entity.velocity != Vec3::ZERO {
+                entity.position += entity.velocity * dt;
+                self.dirty.insert(entity.id);
+            }
+        }
+    }
+}
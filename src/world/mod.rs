@@ -1,7 +1,16 @@
 mod server;
 mod network;
+#[cfg(feature = "client")]
 mod client;
 mod session;
+pub(crate) mod physics;
+pub(crate) mod entity;
+pub(crate) mod time;
 
 pub use server::{ServerWorld, ServerWorldConfig, WorldConfig, *};
+#[cfg(feature = "client")]
 pub use client::{ClientWorld, ClientWorldConfig};
+pub use network::{
+    CaptureDirection, CaptureReader, ChunkStreamingMode, LoopbackNetwork, NetworkConditions,
+    NetworkHandle,
+};
@@ -0,0 +1,411 @@
+use crate::impl_try_from_uint;
+use crate::voxer_network::{NetworkMessageTag, NetworkingError};
+use crate::world::network::{
+    PROTOCOL_VERSION, ReceivedMessage, ServerMessageTag, Transport, WireMessage,
+};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const CAPTURE_MAGIC: [u8; 6] = *b"VXCAP\0";
+const CAPTURE_FORMAT_VERSION: u16 = 1;
+// flushed this often while the network is running and when it shuts down, a crash can lose
+// what was recorded since the last flush
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+const REPLAY_IDLE_WAIT: Duration = Duration::from_millis(100);
+// what fits in a udp datagram, a longer record can only be corrupt
+const MAX_DATAGRAM_SIZE: u32 = u16::MAX as u32;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureDirection {
+    Sent,
+    Received,
+    __Count,
+}
+impl_try_from_uint!(u8 => CaptureDirection);
+
+/// one datagram as it went over the wire, tag and all
+#[derive(Debug, Clone)]
+pub struct CaptureRecord {
+    pub time: Duration, // since the capture started
+    pub direction: CaptureDirection,
+    pub peer: SocketAddr,
+    pub tag: Option<ServerMessageTag>, // None if it didn't decode
+    pub data: Vec<u8>,
+}
+
+fn peek_message_tag(data: &[u8]) -> Option<ServerMessageTag> {
+    let tag_start = data.len().checked_sub(size_of::<NetworkMessageTag>())?;
+    let tag_bytes = data[tag_start..].try_into().ok()?;
+    ServerMessageTag::try_from(NetworkMessageTag::from_be_bytes(tag_bytes)).ok()
+}
+
+/// writes captures, a header followed by records of
+/// `micros u64, direction u8, tag u8, ip [u8; 16], port u16, len u32, data`, little endian
+/// with ipv4 peers stored as mapped ipv6
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    started: Instant,
+    last_flush: Instant,
+    failed: bool, // stops recording after the first error instead of failing the network
+}
+
+impl CaptureWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&CAPTURE_MAGIC)?;
+        out.write_all(&CAPTURE_FORMAT_VERSION.to_le_bytes())?;
+        out.write_all(&PROTOCOL_VERSION.to_le_bytes())?;
+        Ok(Self {
+            out,
+            started: Instant::now(),
+            last_flush: Instant::now(),
+            failed: false,
+        })
+    }
+
+    pub fn record(&mut self, direction: CaptureDirection, peer: SocketAddr, data: &[u8]) {
+        if self.failed {
+            return;
+        }
+        let result = self.write_record(direction, peer, data);
+        self.check(result);
+        self.flush_if_due(Instant::now());
+    }
+
+    pub fn flush_if_due(&mut self, now: Instant) {
+        if now.duration_since(self.last_flush) >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if self.failed {
+            return;
+        }
+        self.last_flush = Instant::now();
+        let result = self.out.flush();
+        self.check(result);
+    }
+
+    fn check(&mut self, result: io::Result<()>) {
+        if let Err(e) = result {
+            println!("capture stopped, failed to write: {:?}", e);
+            self.failed = true;
+        }
+    }
+
+    fn write_record(
+        &mut self,
+        direction: CaptureDirection,
+        peer: SocketAddr,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let micros = self.started.elapsed().as_micros() as u64;
+        let tag = peek_message_tag(data).map_or(u8::MAX, |tag| tag as u8);
+        let ip = match peer.ip() {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        };
+        self.out.write_all(&micros.to_le_bytes())?;
+        self.out.write_all(&[direction as u8, tag])?;
+        self.out.write_all(&ip.octets())?;
+        self.out.write_all(&peer.port().to_le_bytes())?;
+        self.out.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.out.write_all(data)
+    }
+}
+
+pub struct CaptureReader {
+    input: BufReader<File>,
+    pub protocol_version: u32, // of the recording side, tags only mean the same on ours
+}
+
+impl CaptureReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; CAPTURE_MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        let format_version = u16::from_le_bytes(read_array(&mut input)?);
+        if magic != CAPTURE_MAGIC || format_version != CAPTURE_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a capture, or one from another version",
+            ));
+        }
+        let protocol_version = u32::from_le_bytes(read_array(&mut input)?);
+        Ok(Self {
+            input,
+            protocol_version,
+        })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<CaptureRecord>> {
+        let micros = match read_array(&mut self.input) {
+            Ok(bytes) => u64::from_le_bytes(bytes),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let [direction, tag] = read_array(&mut self.input)?;
+        let ip = Ipv6Addr::from(read_array::<16>(&mut self.input)?);
+        let port = u16::from_le_bytes(read_array(&mut self.input)?);
+        let len = u32::from_le_bytes(read_array(&mut self.input)?);
+        if len > MAX_DATAGRAM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "record longer than a datagram",
+            ));
+        }
+        let mut data = vec![0; len as usize];
+        self.input.read_exact(&mut data)?;
+
+        let direction = CaptureDirection::try_from(direction)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad record direction"))?;
+        let ip = match ip.to_ipv4_mapped() {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(ip),
+        };
+        Ok(Some(CaptureRecord {
+            time: Duration::from_micros(micros),
+            direction,
+            peer: SocketAddr::new(ip, port),
+            tag: ServerMessageTag::try_from(tag as NetworkMessageTag).ok(),
+            data,
+        }))
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = io::Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// wraps another transport and records everything going through it
+pub struct RecordingTransport {
+    inner: Box<dyn Transport>,
+    capture: Arc<Mutex<CaptureWriter>>,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Box<dyn Transport>, capture: Arc<Mutex<CaptureWriter>>) -> Self {
+        Self { inner, capture }
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send_to(&self, message: &dyn WireMessage, addr: SocketAddr) -> Result<(), NetworkingError> {
+        self.inner.send_to(message, addr)?;
+        let data = message.encode();
+        self.capture
+            .lock()
+            .record(CaptureDirection::Sent, addr, &data);
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Option<ReceivedMessage> {
+        let message = self.inner.recv();
+        let mut capture = self.capture.lock();
+        match &message {
+            Some(message) => capture.record(CaptureDirection::Received, message.src, &message.data),
+            // the receiving thread polls on when nothing arrived, so a quiet tail is flushed too
+            None => capture.flush_if_due(Instant::now()),
+        }
+        message
+    }
+
+    fn clone_handle(&self) -> Box<dyn Transport> {
+        Box::new(Self {
+            inner: self.inner.clone_handle(),
+            capture: self.capture.clone(),
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+}
+
+struct Replay {
+    reader: CaptureReader,
+    started: Instant,
+    finished: bool,
+}
+
+impl Replay {
+    /// the next datagram the capture received, None once it ran out
+    fn next_received(&mut self) -> Option<CaptureRecord> {
+        while !self.finished {
+            match self.reader.next() {
+                Some(Ok(record)) if record.direction == CaptureDirection::Received => {
+                    return Some(record);
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    println!("replay stopped: {:?}", e);
+                    self.finished = true;
+                }
+                None => {
+                    println!("replay finished");
+                    self.finished = true;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// receives what a capture received, at the pace it was received, and sends nowhere.
+/// given to a `NetworkHandle` it feeds a recording back into a server or client
+#[derive(Clone)]
+pub struct ReplayTransport {
+    replay: Arc<Mutex<Replay>>,
+}
+
+impl ReplayTransport {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let reader = CaptureReader::open(path)?;
+        if reader.protocol_version != PROTOCOL_VERSION {
+            println!(
+                "replaying a capture from protocol {}, ours is {}",
+                reader.protocol_version, PROTOCOL_VERSION
+            );
+        }
+        let replay = Replay {
+            reader,
+            started: Instant::now(),
+            finished: false,
+        };
+        Ok(Self {
+            replay: Arc::new(Mutex::new(replay)),
+        })
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send_to(&self, _: &dyn WireMessage, _: SocketAddr) -> Result<(), NetworkingError> {
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Option<ReceivedMessage> {
+        let (record, started) = {
+            let mut replay = self.replay.lock();
+            (replay.next_received(), replay.started)
+        };
+        let Some(record) = record else {
+            std::thread::sleep(REPLAY_IDLE_WAIT);
+            return None;
+        };
+        let due = started + record.time;
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        Some(ReceivedMessage {
+            data: record.data,
+            src: record.peer,
+        })
+    }
+
+    fn clone_handle(&self) -> Box<dyn Transport> {
+        Box::new(self.clone())
+    }
+
+    /// everything the capture received was replayed
+    fn is_closed(&self) -> bool {
+        self.replay.lock().finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::network::MsgPing;
+    use std::path::PathBuf;
+
+    fn capture_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("voxer-{name}-{}.vxcap", std::process::id()))
+    }
+
+    #[test]
+    fn records_read_back_as_written() {
+        let path = capture_path("round-trip");
+        let v4_peer = SocketAddr::from(([127, 0, 0, 1], 3100));
+        let v6_peer = SocketAddr::from((Ipv6Addr::LOCALHOST, 49152));
+        let ping = MsgPing { id: 7 }.encode();
+        let mut unknown = vec![1, 2, 3];
+        unknown.extend_from_slice(&NetworkMessageTag::MAX.to_be_bytes());
+
+        let mut writer = CaptureWriter::create(&path).unwrap();
+        writer.record(CaptureDirection::Sent, v4_peer, &ping);
+        writer.record(CaptureDirection::Received, v6_peer, &ping);
+        writer.record(CaptureDirection::Received, v4_peer, &unknown);
+        writer.flush();
+        drop(writer);
+
+        let reader = CaptureReader::open(&path).unwrap();
+        assert_eq!(reader.protocol_version, PROTOCOL_VERSION);
+        let records: Vec<CaptureRecord> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        let read: Vec<_> = records
+            .iter()
+            .map(|r| (r.direction, r.peer, r.tag, r.data.as_slice()))
+            .collect();
+        let ping_tag = Some(ServerMessageTag::Ping);
+        assert_eq!(
+            read,
+            [
+                (CaptureDirection::Sent, v4_peer, ping_tag, ping.as_slice()),
+                (
+                    CaptureDirection::Received,
+                    v6_peer,
+                    ping_tag,
+                    ping.as_slice()
+                ),
+                (
+                    CaptureDirection::Received,
+                    v4_peer,
+                    None,
+                    unknown.as_slice()
+                ),
+            ]
+        );
+        assert!(records.is_sorted_by_key(|r| r.time));
+    }
+
+    #[test]
+    fn records_longer_than_a_datagram_are_rejected() {
+        let path = capture_path("oversized");
+        let mut writer = CaptureWriter::create(&path).unwrap();
+        writer.record(
+            CaptureDirection::Received,
+            SocketAddr::from(([127, 0, 0, 1], 3100)),
+            &[0; 4],
+        );
+        writer.flush();
+        drop(writer);
+        // the record ends in its 4 bytes of data, right after their length
+        let mut bytes = std::fs::read(&path).unwrap();
+        let len_start = bytes.len() - 4 - size_of::<u32>();
+        let len = MAX_DATAGRAM_SIZE + 1;
+        bytes[len_start..len_start + 4].copy_from_slice(&len.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut reader = CaptureReader::open(&path).unwrap();
+        let error = reader.next().unwrap().unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::world::server::world::{CHUNK_VOLUME, VoxelChunkBlocks};
+use rustc_hash::FxHashMap;
+
+// bump whenever the layout of any encoding changes, decoders reject other versions
+pub const CHUNK_CODEC_VERSION: u8 = 1;
+
+const HEADER_SIZE: usize = 2; // version, encoding
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkEncoding {
+    Uniform, // a single block: u16
+    Palette, // palette len: u16, palette: [u16], then bit-packed palette indices
+    Rle,     // runs of (len: u16, block: u16) in blocks[x][y][z] order
+    __Count,
+}
+crate::impl_try_from_uint!(u8 => ChunkEncoding);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkCodecError {
+    UnsupportedVersion(u8),
+    UnknownEncoding(u8),
+    Truncated,
+    Malformed,
+}
+
+/// encodes `blocks` with whichever encoding comes out smallest
+pub fn encode(blocks: &VoxelChunkBlocks) -> Vec<u8> {
+    let flat = flat_blocks(blocks);
+    let (palette, indices) = build_palette(flat);
+    match palette.len() {
+        1 => encode_uniform(palette[0]),
+        _ => {
+            let palette_encoded = encode_palette(&palette, &indices);
+            let rle_encoded = encode_rle(flat);
+            match rle_encoded.len() < palette_encoded.len() {
+                true => rle_encoded,
+                false => palette_encoded,
+            }
+        }
+    }
+}
+
+/// returns the blocks and their voxel count
+pub fn decode(bytes: &[u8]) -> Result<(VoxelChunkBlocks, u32), ChunkCodecError> {
+    let [version, encoding, payload @ ..] = bytes else {
+        return Err(ChunkCodecError::Truncated);
+    };
+    if *version != CHUNK_CODEC_VERSION {
+        return Err(ChunkCodecError::UnsupportedVersion(*version));
+    }
+    let encoding = ChunkEncoding::try_from(*encoding)
+        .map_err(|_| ChunkCodecError::UnknownEncoding(*encoding))?;
+
+    let mut blocks = VoxelChunkBlocks::default();
+    let flat: &mut [u16; CHUNK_VOLUME] = bytemuck::cast_mut(&mut blocks);
+    match encoding {
+        ChunkEncoding::Uniform => flat.fill(read_u16(payload, 0)?),
+        ChunkEncoding::Palette => decode_palette(payload, flat)?,
+        ChunkEncoding::Rle => decode_rle(payload, flat)?,
+        ChunkEncoding::__Count => unreachable!(),
+    }
+    let voxel_count = blocks
+        .as_flattened()
+        .as_flattened()
+        .iter()
+        .filter(|b| !b.is_transparent())
+        .count() as u32;
+    Ok((blocks, voxel_count))
+}
+
+fn flat_blocks(blocks: &VoxelChunkBlocks) -> &[u16; CHUNK_VOLUME] {
+    bytemuck::cast_ref(blocks)
+}
+
+fn header(encoding: ChunkEncoding, payload_size: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload_size);
+    bytes.push(CHUNK_CODEC_VERSION);
+    bytes.push(encoding as u8);
+    bytes
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ChunkCodecError> {
+    let b = bytes
+        .get(offset..offset + 2)
+        .ok_or(ChunkCodecError::Truncated)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+/// bits needed to index a palette of `len` entries, at least 1
+fn index_bits(len: usize) -> usize {
+    (usize::BITS - (len - 1).leading_zeros()).max(1) as usize
+}
+
+fn build_palette(flat: &[u16; CHUNK_VOLUME]) -> (Vec<u16>, Vec<u16>) {
+    let mut palette = Vec::new();
+    let mut palette_index: FxHashMap<u16, u16> = FxHashMap::default();
+    let indices = flat
+        .iter()
+        .map(|&block| {
+            *palette_index.entry(block).or_insert_with(|| {
+                palette.push(block);
+                (palette.len() - 1) as u16
+            })
+        })
+        .collect();
+    (palette, indices)
+}
+
+fn encode_uniform(block: u16) -> Vec<u8> {
+    let mut bytes = header(ChunkEncoding::Uniform, 2);
+    bytes.extend_from_slice(&block.to_le_bytes());
+    bytes
+}
+
+fn encode_palette(palette: &[u16], indices: &[u16]) -> Vec<u8> {
+    let bits = index_bits(palette.len());
+    let packed_size = (CHUNK_VOLUME * bits).div_ceil(8);
+    let mut bytes = header(ChunkEncoding::Palette, 2 + palette.len() * 2 + packed_size);
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for block in palette {
+        bytes.extend_from_slice(&block.to_le_bytes());
+    }
+
+    // lsb-first bitstream
+    let packed_start = bytes.len();
+    bytes.resize(packed_start + packed_size, 0);
+    let packed = &mut bytes[packed_start..];
+    for (i, &index) in indices.iter().enumerate() {
+        let bit = i * bits;
+        let value = (index as u32) << (bit % 8);
+        for (byte_offset, byte) in value.to_le_bytes().iter().enumerate() {
+            if let Some(dst) = packed.get_mut(bit / 8 + byte_offset) {
+                *dst |= byte;
+            }
+        }
+    }
+    bytes
+}
+
+fn decode_palette(payload: &[u8], flat: &mut [u16; CHUNK_VOLUME]) -> Result<(), ChunkCodecError> {
+    let len = read_u16(payload, 0)? as usize;
+    if len == 0 || len > CHUNK_VOLUME {
+        return Err(ChunkCodecError::Malformed);
+    }
+    let palette = (0..len)
+        .map(|i| read_u16(payload, 2 + i * 2))
+        .collect::<Result<Vec<u16>, _>>()?;
+
+    let bits = index_bits(len);
+    let packed_start = 2 + len * 2;
+    let packed = payload
+        .get(packed_start..packed_start + (CHUNK_VOLUME * bits).div_ceil(8))
+        .ok_or(ChunkCodecError::Truncated)?;
+    let mask = (1u32 << bits) - 1;
+    for (i, block) in flat.iter_mut().enumerate() {
+        let bit = i * bits;
+        let mut word = [0u8; 4];
+        for (byte_offset, byte) in word.iter_mut().enumerate() {
+            *byte = packed.get(bit / 8 + byte_offset).copied().unwrap_or(0);
+        }
+        let index = (u32::from_le_bytes(word) >> (bit % 8)) & mask;
+        *block = *palette
+            .get(index as usize)
+            .ok_or(ChunkCodecError::Malformed)?;
+    }
+    Ok(())
+}
+
+fn encode_rle(flat: &[u16; CHUNK_VOLUME]) -> Vec<u8> {
+    let mut bytes = header(ChunkEncoding::Rle, 64);
+    let mut i = 0;
+    while i < CHUNK_VOLUME {
+        let block = flat[i];
+        let run = flat[i..].iter().take_while(|&&b| b == block).count();
+        // runs are at most CHUNK_VOLUME (4096) long, which fits a u16
+        bytes.extend_from_slice(&(run as u16).to_le_bytes());
+        bytes.extend_from_slice(&block.to_le_bytes());
+        i += run;
+    }
+    bytes
+}
+
+fn decode_rle(payload: &[u8], flat: &mut [u16; CHUNK_VOLUME]) -> Result<(), ChunkCodecError> {
+    if payload.len() % 4 != 0 {
+        return Err(ChunkCodecError::Truncated);
+    }
+    let mut i = 0;
+    for offset in (0..payload.len()).step_by(4) {
+        let run = read_u16(payload, offset)? as usize;
+        let block = read_u16(payload, offset + 2)?;
+        let dst = flat
+            .get_mut(i..i + run)
+            .filter(|_| run != 0)
+            .ok_or(ChunkCodecError::Malformed)?;
+        dst.fill(block);
+        i += run;
+    }
+    match i == CHUNK_VOLUME {
+        true => Ok(()),
+        false => Err(ChunkCodecError::Malformed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOLID: u16 = 0x8000;
+
+    fn chunk(block: impl Fn(usize) -> u16) -> VoxelChunkBlocks {
+        let mut blocks = VoxelChunkBlocks::default();
+        let flat: &mut [u16; CHUNK_VOLUME] = bytemuck::cast_mut(&mut blocks);
+        for (i, b) in flat.iter_mut().enumerate() {
+            *b = block(i);
+        }
+        blocks
+    }
+
+    fn round_trip(blocks: &VoxelChunkBlocks, encoding: ChunkEncoding) -> Vec<u8> {
+        let encoded = encode(blocks);
+        assert_eq!(encoded[0], CHUNK_CODEC_VERSION);
+        assert_eq!(encoded[1], encoding as u8);
+        let (decoded, voxel_count) = decode(&encoded).unwrap();
+        assert_eq!(flat_blocks(&decoded), flat_blocks(blocks));
+        let solid = flat_blocks(blocks)
+            .iter()
+            .filter(|&&b| b & SOLID != 0)
+            .count();
+        assert_eq!(voxel_count, solid as u32);
+        encoded
+    }
+
+    fn error(bytes: &[u8]) -> Option<ChunkCodecError> {
+        decode(bytes).err()
+    }
+
+    #[test]
+    fn uniform() {
+        let encoded = round_trip(&chunk(|_| SOLID | 3), ChunkEncoding::Uniform);
+        assert_eq!(encoded.len(), HEADER_SIZE + 2);
+        round_trip(&chunk(|_| 0), ChunkEncoding::Uniform);
+    }
+
+    #[test]
+    fn palette_1_bit() {
+        // alternating blocks make the worst case for rle
+        let encoded = round_trip(&chunk(|i| (i % 2) as u16 * SOLID), ChunkEncoding::Palette);
+        assert_eq!(encoded.len(), HEADER_SIZE + 2 + 2 * 2 + CHUNK_VOLUME / 8);
+    }
+
+    #[test]
+    fn palette_12_bit() {
+        let encoded = round_trip(&chunk(|i| i as u16 | SOLID), ChunkEncoding::Palette);
+        assert_eq!(
+            encoded.len(),
+            HEADER_SIZE + 2 + CHUNK_VOLUME * 2 + CHUNK_VOLUME * 12 / 8
+        );
+    }
+
+    #[test]
+    fn rle() {
+        let encoded = round_trip(
+            &chunk(|i| if i < CHUNK_VOLUME / 2 { 0 } else { SOLID | 1 }),
+            ChunkEncoding::Rle,
+        );
+        assert_eq!(encoded.len(), HEADER_SIZE + 2 * 4);
+    }
+
+    #[test]
+    fn rejects_truncated() {
+        assert_eq!(error(&[]), Some(ChunkCodecError::Truncated));
+        assert_eq!(
+            error(&[CHUNK_CODEC_VERSION]),
+            Some(ChunkCodecError::Truncated)
+        );
+        let uniform = encode(&chunk(|_| SOLID));
+        assert_eq!(
+            error(&uniform[..uniform.len() - 1]),
+            Some(ChunkCodecError::Truncated)
+        );
+        let palette = encode(&chunk(|i| (i % 2) as u16));
+        assert_eq!(
+            error(&palette[..palette.len() - 1]),
+            Some(ChunkCodecError::Truncated)
+        );
+        let rle = encode(&chunk(|i| (i < 10) as u16));
+        assert_eq!(
+            error(&rle[..rle.len() - 1]),
+            Some(ChunkCodecError::Truncated)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        assert_eq!(
+            error(&[CHUNK_CODEC_VERSION + 1, 0, 0, 0]),
+            Some(ChunkCodecError::UnsupportedVersion(CHUNK_CODEC_VERSION + 1))
+        );
+        let unknown = ChunkEncoding::__Count as u8;
+        assert_eq!(
+            error(&[CHUNK_CODEC_VERSION, unknown, 0, 0]),
+            Some(ChunkCodecError::UnknownEncoding(unknown))
+        );
+    }
+
+    fn rle_bytes(runs: &[(u16, u16)]) -> Vec<u8> {
+        let mut bytes = vec![CHUNK_CODEC_VERSION, ChunkEncoding::Rle as u8];
+        for (run, block) in runs {
+            bytes.extend_from_slice(&run.to_le_bytes());
+            bytes.extend_from_slice(&block.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn rejects_zero_run() {
+        let bytes = rle_bytes(&[(0, 1), (CHUNK_VOLUME as u16, 0)]);
+        assert_eq!(error(&bytes), Some(ChunkCodecError::Malformed));
+    }
+
+    #[test]
+    fn rejects_over_long_runs() {
+        let bytes = rle_bytes(&[(CHUNK_VOLUME as u16 + 1, 0)]);
+        assert_eq!(error(&bytes), Some(ChunkCodecError::Malformed));
+        let bytes = rle_bytes(&[(CHUNK_VOLUME as u16 - 1, 0), (2, 1)]);
+        assert_eq!(error(&bytes), Some(ChunkCodecError::Malformed));
+        // and runs that stop short of the chunk
+        let bytes = rle_bytes(&[(CHUNK_VOLUME as u16 - 1, 0)]);
+        assert_eq!(error(&bytes), Some(ChunkCodecError::Malformed));
+    }
+}
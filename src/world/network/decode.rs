@@ -0,0 +1,96 @@
+use crate::voxer_network::NetworkMessageTag;
+use crate::world::network::protocol::{Endpoint, PayloadLayout, direction, payload_layout};
+use crate::world::network::{ServerMessageTag, block_deltas, encoded_chunk_payload};
+use std::cmp::Ordering;
+
+/// why a received datagram was dropped instead of handed to the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    MissingTag,
+    UnknownTag(NetworkMessageTag),
+    UnexpectedTag(ServerMessageTag), // a tag nothing sends on its own, or not sent to this end
+    Truncated {
+        tag: ServerMessageTag,
+        expected: usize,
+        actual: usize,
+    },
+    WrongSize {
+        tag: ServerMessageTag,
+        expected: usize,
+        actual: usize,
+    },
+    Malformed(ServerMessageTag),
+}
+
+impl DecodeError {
+    fn kind_index(&self) -> usize {
+        match self {
+            DecodeError::MissingTag => 0,
+            DecodeError::UnknownTag(_) => 1,
+            DecodeError::UnexpectedTag(_) => 2,
+            DecodeError::Truncated { .. } => 3,
+            DecodeError::WrongSize { .. } => 4,
+            DecodeError::Malformed(_) => 5,
+        }
+    }
+}
+
+/// counts of dropped datagrams by kind of error
+#[derive(Debug, Default)]
+pub struct DecodeStats {
+    counts: [u64; 6],
+}
+
+impl DecodeStats {
+    /// counts `error`, returning how many of its kind were seen so far
+    pub fn record(&mut self, error: &DecodeError) -> u64 {
+        let count = &mut self.counts[error.kind_index()];
+        *count += 1;
+        *count
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl ServerMessageTag {
+    /// checks `payload` fits this tag's message, so deserializing it can't fail, and that
+    /// the message is one `receiver` handles
+    pub fn validate_payload(&self, payload: &[u8], receiver: Endpoint) -> Result<(), DecodeError> {
+        let tag = *self;
+        if !direction(tag).reaches(receiver) {
+            return Err(DecodeError::UnexpectedTag(tag));
+        }
+        let (expected, actual) = match payload_layout(tag) {
+            PayloadLayout::Fixed(size) => (size, payload.len()),
+            // only the version has to be there, the rest is checked once it is known to match
+            PayloadLayout::Handshake(_) if payload.len() >= size_of::<u32>() => return Ok(()),
+            PayloadLayout::Handshake(_) => (size_of::<u32>(), payload.len()),
+            PayloadLayout::EncodedChunk => {
+                return encoded_chunk_payload(payload)
+                    .map(|_| ())
+                    .ok_or(DecodeError::Malformed(tag));
+            }
+            PayloadLayout::BlockDeltas => {
+                return block_deltas(payload)
+                    .map(|_| ())
+                    .ok_or(DecodeError::Malformed(tag));
+            }
+            PayloadLayout::Unsent => return Err(DecodeError::UnexpectedTag(tag)),
+        };
+        match actual.cmp(&expected) {
+            Ordering::Equal => Ok(()),
+            Ordering::Less => Err(DecodeError::Truncated {
+                tag,
+                expected,
+                actual,
+            }),
+            Ordering::Greater => Err(DecodeError::WrongSize {
+                tag,
+                expected,
+                actual,
+            }),
+        }
+    }
+}
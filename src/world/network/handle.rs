@@ -1,28 +1,84 @@
-use crate::world::network::{ServerMessage, process_message};
+use crate::world::network::protocol::{Delivery, delivery};
+use crate::world::network::reliability::{Reliability, Reliable};
+use crate::world::network::transport::NetworkMessage;
+use crate::world::network::capture::{CaptureWriter, RecordingTransport, ReplayTransport};
+use crate::world::network::{
+    DecodeStats, Endpoint, LoopbackNetwork, MsgAck, NetworkConditions, ServerMessage,
+    ServerMessageTag, SimulatedTransport, Transport, UdpTransport, WireMessage, process_message,
+};
 use crossbeam::channel;
 use crossbeam::channel::TryIter;
-use std::net::ToSocketAddrs;
-use voxer_network::{NetworkSerializable, NetworkingError, UdpChannel};
+use parking_lot::Mutex;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use voxer_network::{
+    NetworkDeserializable, NetworkMessageConfig, NetworkSerializable, NetworkingError,
+};
 
 pub struct NetworkHandle {
-    channel: UdpChannel,
+    transport: Box<dyn Transport>,
+    reliability: Arc<Mutex<Reliability>>, // shared with the receiving thread, which acks
     thread_handle: Option<std::thread::JoinHandle<()>>,
     send_handle: Option<channel::Sender<ServerMessage>>,
     recv_handle: channel::Receiver<ServerMessage>,
+    capture: Option<Arc<Mutex<CaptureWriter>>>, // shared with the recording transport
 }
 
 impl NetworkHandle {
     pub fn bind<A: ToSocketAddrs>(addr: A, buffer_size: usize) -> Self {
-        let channel = UdpChannel::bind(addr, buffer_size);
+        Self::with_transport(Box::new(UdpTransport::bind(addr, buffer_size)))
+    }
+
+    /// an endpoint of an in-process network, for a server and client sharing a process
+    pub fn loopback(network: &LoopbackNetwork, addr: SocketAddr) -> Self {
+        Self::with_transport(Box::new(network.bind(addr)))
+    }
+
+    pub fn with_transport(transport: Box<dyn Transport>) -> Self {
         let (send_handle, recv_handle) = channel::unbounded::<ServerMessage>();
         Self {
-            channel,
+            transport,
+            reliability: Arc::new(Mutex::new(Reliability::new())),
             thread_handle: None,
             send_handle: Some(send_handle),
             recv_handle,
+            capture: None,
         }
     }
 
+    /// sends through a simulated link with `conditions` from now on, has to be set before `listen`
+    pub fn with_conditions(self, conditions: NetworkConditions) -> Self {
+        let transport = Box::new(SimulatedTransport::new(self.transport, conditions));
+        Self { transport, ..self }
+    }
+
+    /// writes every datagram sent and received from now on to a capture at `path`,
+    /// has to be set before `listen`
+    pub fn with_recorder<P: AsRef<Path>>(self, path: P) -> io::Result<Self> {
+        let capture = Arc::new(Mutex::new(CaptureWriter::create(path)?));
+        let transport = Box::new(RecordingTransport::new(self.transport, capture.clone()));
+        Ok(Self {
+            transport,
+            capture: Some(capture),
+            ..self
+        })
+    }
+
+    /// writes out whatever the capture still buffers, call when the network is shut down
+    pub fn flush_capture(&self) {
+        if let Some(capture) = &self.capture {
+            capture.lock().flush();
+        }
+    }
+
+    /// a handle that receives what a capture at `path` received, and whose sends go nowhere
+    pub fn replay<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::with_transport(Box::new(ReplayTransport::open(path)?)))
+    }
+
     pub fn try_iter_messages(&self) -> TryIter<'_, ServerMessage> {
         self.recv_handle.try_iter()
     }
@@ -34,27 +90,116 @@ impl NetworkHandle {
         out.extend(self.recv_handle.try_iter().take(max))
     }
 
+    /// sends as is, for unreliable messages only, see `send`
     pub fn send_to(
         &self,
-        data: Box<dyn NetworkSerializable>,
-        addr: &impl ToSocketAddrs,
+        data: Box<dyn WireMessage>,
+        addr: &SocketAddr,
     ) -> Result<(), NetworkingError> {
-        self.channel.send_to(data, addr)
+        self.transport.send_to(data.as_ref(), *addr)
     }
 
-    pub fn listen(&mut self) {
-        let net = self.channel.clone_handle();
+    /// sends `message` the way its tag is marked for, reliable messages are resent until acked
+    pub(crate) fn send<M: NetworkMessage>(
+        &self,
+        message: M,
+        addr: &SocketAddr,
+    ) -> Result<(), NetworkingError>
+    where
+        Reliable<M>: NetworkSerializable,
+    {
+        let tag = ServerMessageTag::try_from(message.tag()).unwrap();
+        match delivery(tag) {
+            Delivery::Unreliable => self.transport.send_to(&message, *addr),
+            Delivery::Reliable(channel) => {
+                let now = Instant::now();
+                let reliable = self
+                    .reliability
+                    .lock()
+                    .prepare(message, channel, *addr, now);
+                self.transport.send_to(&reliable, *addr)
+            }
+        }
+    }
+
+    /// has to be called regularly for reliable messages to be resent, returns the peers to
+    /// disconnect since an ordered channel to them broke
+    pub fn resend_unacked(&self) -> Vec<SocketAddr> {
+        self.reliability
+            .lock()
+            .poll_resends(Instant::now(), |addr, message| {
+                self.transport.send_to(message, addr).unwrap();
+            })
+    }
+
+    /// starts sending to `addr` as a new connection, called by both ends when connecting
+    pub fn reset_peer(&self, addr: SocketAddr) {
+        self.reliability.lock().reset_peer(addr);
+    }
+
+    /// drops what is kept of `addr` for reliable messages, call once it disconnected
+    pub fn forget_peer(&self, addr: SocketAddr) {
+        self.reliability.lock().forget_peer(addr);
+    }
+
+    /// starts receiving as `endpoint`, messages not sent to that end are dropped
+    pub fn listen(&mut self, endpoint: Endpoint) {
+        let net = self.transport.clone_handle();
         let send_handle = self.send_handle.take().unwrap();
+        let reliability = self.reliability.clone();
         self.thread_handle = Some(std::thread::spawn(move || {
-            NetworkHandle::recv_loop(net, send_handle);
+            NetworkHandle::recv_loop(net, endpoint, send_handle, reliability);
         }));
     }
 
-    fn recv_loop(mut net: UdpChannel, send_channel: channel::Sender<ServerMessage>) {
-        loop {
-            if let Some(msg) = net.recv_single() {
-                let server_msg = process_message(msg);
-                send_channel.send(server_msg).unwrap()
+    fn recv_loop(
+        mut net: Box<dyn Transport>,
+        endpoint: Endpoint,
+        send_channel: channel::Sender<ServerMessage>,
+        reliability: Arc<Mutex<Reliability>>,
+    ) {
+        let mut decode_stats = DecodeStats::default();
+        // until the transport closes or the handle, and with it the receiving end, is dropped
+        while !net.is_closed() {
+            let Some(msg) = net.recv() else {
+                continue;
+            };
+            let src = msg.src;
+            let (server_msg, header) = match process_message(msg, endpoint) {
+                Ok(processed) => processed,
+                Err(e) => {
+                    // malformed datagrams are dropped, logging fewer as a kind of error repeats
+                    let count = decode_stats.record(&e);
+                    if count.is_power_of_two() {
+                        println!(
+                            "dropped message from {src}: {:?} ({count} of this kind, {} total)",
+                            e,
+                            decode_stats.total()
+                        );
+                    }
+                    continue;
+                }
+            };
+            if server_msg.tag == ServerMessageTag::Ack {
+                let ack = MsgAck::deserialize(server_msg.message.data);
+                reliability.lock().on_ack(src, &ack);
+                continue;
+            }
+            let Some(header) = header else {
+                if send_channel.send(server_msg).is_err() {
+                    return;
+                }
+                continue;
+            };
+            let mut delivered = true;
+            let ack = reliability.lock().receive(src, header, server_msg, |msg| {
+                delivered &= send_channel.send(msg).is_ok();
+            });
+            if !delivered {
+                return;
+            }
+            if let Some(ack) = ack {
+                net.send_to(&ack, src).unwrap();
             }
         }
     }
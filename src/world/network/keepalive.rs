@@ -0,0 +1,67 @@
+use crate::world::network::{MsgPing, MsgPong};
+use std::time::{Duration, Instant};
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// tracks liveness and round trip time of one side of a connection
+#[derive(Debug)]
+pub struct Keepalive {
+    last_received: Instant,
+    last_ping_sent: Instant,
+    next_ping_id: u32,
+    pending_ping: Option<(u32, Instant)>, // only the latest ping is timed
+    rtt: Option<Duration>,
+}
+
+impl Keepalive {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            last_received: now,
+            last_ping_sent: now,
+            next_ping_id: 0,
+            pending_ping: None,
+            rtt: None,
+        }
+    }
+
+    /// any message from the peer counts as a sign of life
+    pub fn on_received(&mut self, now: Instant) {
+        self.last_received = now;
+    }
+
+    pub fn poll_ping(&mut self, now: Instant) -> Option<MsgPing> {
+        if now.duration_since(self.last_ping_sent) < PING_INTERVAL {
+            return None;
+        }
+        let id = self.next_ping_id;
+        self.next_ping_id = self.next_ping_id.wrapping_add(1);
+        self.last_ping_sent = now;
+        self.pending_ping = Some((id, now));
+        Some(MsgPing { id })
+    }
+
+    pub fn on_pong(&mut self, pong: &MsgPong, now: Instant) {
+        let Some((id, sent_at)) = self.pending_ping else {
+            return;
+        };
+        if pong.id != id {
+            return; // answer to a ping we already gave up timing
+        }
+        self.pending_ping = None;
+        let sample = now.duration_since(sent_at);
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => (rtt * 7 + sample) / 8,
+            None => sample,
+        });
+    }
+
+    pub fn is_timed_out(&self, now: Instant) -> bool {
+        now.duration_since(self.last_received) >= CONNECTION_TIMEOUT
+    }
+
+    /// smoothed round trip time, None until the first pong arrives
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+}
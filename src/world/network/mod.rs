@@ -1,13 +1,39 @@
+mod capture;
+pub mod chunk_codec;
+mod decode;
 mod handle;
+mod keepalive;
+mod protocol;
+mod reliability;
+mod simulator;
+mod transport;
 
 use crate::impl_try_from_uint;
-use crate::voxer_network::{NetworkMessageTag, ReceivedMessage};
+use crate::voxer_network::{NetworkMessageTag, NetworkingError};
+use crate::world::entity::{ENTITY_DATA_SIZE, Entity, EntityData, EntityId, EntityKind};
 use crate::world::server::VoxelChunkBlocks;
+use crate::world::server::block::VoxelBlock;
+use crate::world::time::WorldTime;
 use bytemuck::{Pod, Zeroable};
-use glam::{IVec3, Vec3};
+use glam::{IVec3, Quat, Vec3};
+use std::net::SocketAddr;
+pub use capture::{CaptureDirection, CaptureReader, CaptureRecord};
+pub use decode::{DecodeError, DecodeStats};
 pub use handle::NetworkHandle;
+pub use keepalive::Keepalive;
+pub use protocol::{Endpoint, PROTOCOL_VERSION, handshake_protocol_version};
+use protocol::Delivery;
+use reliability::ReliableHeader;
+pub use simulator::{NetworkConditions, SimulatedTransport};
+pub use transport::{LoopbackNetwork, Transport, UdpTransport, WireMessage};
 use voxer_macros::network_message;
 
+#[derive(Debug)]
+pub struct ReceivedMessage {
+    pub data: Vec<u8>,
+    pub src: SocketAddr,
+}
+
 #[derive(Debug)]
 pub struct ServerMessage {
     pub tag: ServerMessageTag,
@@ -15,7 +41,7 @@ pub struct ServerMessage {
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ServerMessageTag {
     ConnectRequest,
     ConnectDeny,
@@ -27,6 +53,8 @@ pub enum ServerMessageTag {
     ChunkDataDeny,
     ChunkData,
     ChunkDataEmpty,
+    ChunkDataEncoded,
+    ChunkDataAck,
     UpdateChunksRequest,
     UpdateChunksDeny,
     UpdateChunks,
@@ -35,7 +63,22 @@ pub enum ServerMessageTag {
     SetPositionDeny,
     SetPosition,
 
+    EntitySpawn,
+    EntityDespawn,
+    EntityUpdate,
+
+    PlayerJoin,
+    PlayerLeave,
+    PlayerSnapshot,
+
+    ChatRequest,
+    Chat,
+
+    WorldTime,
+
     Ping,
+    Pong,
+    Ack,
     __Count,
 }
 impl_try_from_uint!(NetworkMessageTag => ServerMessageTag);
@@ -46,11 +89,137 @@ impl ServerMessageTag {
     }
 }
 
+pub const MAX_PLAYER_NAME_LEN: usize = 16;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[network_message(tag = ServerMessageTag::ConnectRequest.as_tag())]
 pub struct MsgConnectRequest {
-    pub byte: u8,
+    pub protocol_version: u32,
+    pub name: [u8; MAX_PLAYER_NAME_LEN], // utf8, `name_len` bytes used
+    pub name_len: u8,
+    _pad: [u8; 3],
+}
+
+impl MsgConnectRequest {
+    pub fn new(name: &str) -> Self {
+        // names longer than `MAX_PLAYER_NAME_LEN` bytes are truncated
+        let (name, name_len) = encode_text(name);
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            name,
+            name_len: name_len as u8,
+            _pad: [0; 3],
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        decode_text(&self.name, self.name_len as usize)
+    }
+}
+
+/// copies as much of `text` as fits, cut at a char boundary, and how many bytes that is
+fn encode_text<const N: usize>(text: &str) -> ([u8; N], usize) {
+    let mut len = text.len().min(N);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    let mut bytes = [0; N];
+    bytes[..len].copy_from_slice(&text.as_bytes()[..len]);
+    (bytes, len)
+}
+
+fn decode_text(bytes: &[u8], len: usize) -> Option<&str> {
+    std::str::from_utf8(bytes.get(..len)?).ok()
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::Connect.as_tag())]
+pub struct MsgConnect {
+    pub player_id: u32,
+    pub world: u32,
+    pub seed: i32,
+    pub spawn: Vec3, // feet position
+    pub chunk_streaming: u8,
+    _pad: [u8; 3],
+}
+
+impl MsgConnect {
+    pub fn new(
+        player_id: u32,
+        world: u32,
+        seed: i32,
+        spawn: Vec3,
+        chunk_streaming: ChunkStreamingMode,
+    ) -> Self {
+        Self {
+            player_id,
+            world,
+            seed,
+            spawn,
+            chunk_streaming: chunk_streaming as u8,
+            _pad: [0; 3],
+        }
+    }
+
+    pub fn chunk_streaming(&self) -> Option<ChunkStreamingMode> {
+        ChunkStreamingMode::try_from(self.chunk_streaming).ok()
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStreamingMode {
+    Pull, // the client requests the chunks it misses
+    Push, // the server sends chunks around the player and the client acks them
+    __Count,
+}
+impl_try_from_uint!(u8 => ChunkStreamingMode);
+
+impl ChunkStreamingMode {
+    /// `push` or `pull`, the way it is given on the command line
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "pull" => Some(ChunkStreamingMode::Pull),
+            "push" => Some(ChunkStreamingMode::Push),
+            _ => None,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectDenyReason {
+    ServerFull,
+    BadVersion,
+    DuplicateName,
+    InvalidName,
+    __Count,
+}
+impl_try_from_uint!(u8 => ConnectDenyReason);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::ConnectDeny.as_tag())]
+pub struct MsgConnectDeny {
+    pub protocol_version: u32, // the server's, so a client can tell which side is outdated
+    pub reason: u8,
+    _pad: [u8; 3],
+}
+
+impl MsgConnectDeny {
+    pub fn new(reason: ConnectDenyReason) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            reason: reason as u8,
+            _pad: [0; 3],
+        }
+    }
+
+    pub fn reason(&self) -> Option<ConnectDenyReason> {
+        ConnectDenyReason::try_from(self.reason).ok()
+    }
 }
 
 // todo find a better place for consts like this
@@ -80,6 +249,33 @@ impl MsgChunkDataRequest {
         request.positions[..positions.len()].copy_from_slice(positions);
         request
     }
+
+    /// None if the count is out of range, the sender can't be trusted to set it right
+    pub fn positions(&self) -> Option<&[IVec3]> {
+        self.positions.get(..self.count as usize)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::ChunkDataAck.as_tag())]
+pub struct MsgChunkDataAck {
+    pub count: u8,
+    _pad: [u8; 3],
+    pub positions: [IVec3; MAX_CHUNKS_PER_BATCH],
+}
+
+impl MsgChunkDataAck {
+    pub fn with_positions(positions: &[IVec3]) -> Self {
+        let mut ack = Self::zeroed();
+        ack.count = positions.len() as u8;
+        ack.positions[..positions.len()].copy_from_slice(positions);
+        ack
+    }
+
+    pub fn positions(&self) -> &[IVec3] {
+        &self.positions[..(self.count as usize).min(MAX_CHUNKS_PER_BATCH)]
+    }
 }
 
 #[repr(C)]
@@ -98,33 +294,595 @@ pub struct MsgChunkDataEmpty {
     pub position: IVec3,          // 0..11
 }
 
+// encoded chunks are sent in the smallest bucket that fits, all buckets share a tag and a
+// header so the receiver doesn't need to know which one it got
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct EncodedChunkHeader {
+    position: IVec3,
+    len: u32, // used bytes of the payload
+}
+
+macro_rules! encoded_chunk_buckets {
+    ($($name:ident => $size:expr),* $(,)?) => {
+        $(
+            #[repr(C)]
+            #[derive(Debug, Clone, Copy, Pod, Zeroable)]
+            #[network_message(tag = ServerMessageTag::ChunkDataEncoded.as_tag())]
+            struct $name {
+                header: EncodedChunkHeader,
+                bytes: [u8; $size],
+            }
+        )*
+
+        pub(crate) const ENCODED_CHUNK_BUCKETS: &[usize] = &[$($size),*];
+
+        /// wraps a `chunk_codec` payload in the smallest message that fits it, along with that
+        /// message's size, None if it is no smaller than sending the raw blocks
+        pub fn encoded_chunk_message(
+            position: IVec3,
+            encoded: &[u8],
+        ) -> Option<(Box<dyn WireMessage>, usize)> {
+            let header = EncodedChunkHeader {
+                position,
+                len: encoded.len() as u32,
+            };
+            $(
+                if encoded.len() <= $size {
+                    let mut msg = $name::zeroed();
+                    msg.header = header;
+                    msg.bytes[..encoded.len()].copy_from_slice(encoded);
+                    return Some((Box::new(msg), size_of::<$name>()));
+                }
+            )*
+            None
+        }
+    };
+}
+
+encoded_chunk_buckets! {
+    MsgChunkDataEncoded64 => 64,
+    MsgChunkDataEncoded256 => 256,
+    MsgChunkDataEncoded1K => 1024,
+    MsgChunkDataEncoded2K => 2048,
+    MsgChunkDataEncoded4K => 4096,
+    MsgChunkDataEncoded6K => 6144,
+}
+
+/// splits a received `ChunkDataEncoded` message into its position and codec payload
+pub fn encoded_chunk_payload(data: &[u8]) -> Option<(IVec3, &[u8])> {
+    let header_size = size_of::<EncodedChunkHeader>();
+    let header: EncodedChunkHeader =
+        bytemuck::try_pod_read_unaligned(data.get(..header_size)?).ok()?;
+    let payload = data.get(header_size..header_size + header.len as usize)?;
+    Some((header.position, payload))
+}
+
+pub const MAX_BLOCK_DELTAS_PER_BATCH: usize = 128;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct BlockDelta {
+    pub chunk: IVec3,
+    pub index: u16, // local index within the chunk, see `VoxelChunk::local_index`
+    pub block: VoxelBlock,
+}
+
+// block deltas are sent in the smallest bucket that fits, like encoded chunks
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct BlockDeltasHeader {
+    count: u32, // used deltas
+}
+
+macro_rules! block_delta_buckets {
+    ($($name:ident => $size:expr),* $(,)?) => {
+        $(
+            /// changed blocks of one or more chunks
+            #[repr(C)]
+            #[derive(Debug, Clone, Copy, Pod, Zeroable)]
+            #[network_message(tag = ServerMessageTag::UpdateChunks.as_tag())]
+            struct $name {
+                header: BlockDeltasHeader,
+                deltas: [BlockDelta; $size],
+            }
+        )*
+
+        pub(crate) const BLOCK_DELTA_BUCKETS: &[usize] = &[$($size),*];
+
+        /// sends up to `MAX_BLOCK_DELTAS_PER_BATCH` deltas in the smallest message that fits them
+        pub fn send_block_deltas(
+            network: &NetworkHandle,
+            deltas: &[BlockDelta],
+            addr: &SocketAddr,
+        ) -> Result<(), NetworkingError> {
+            let header = BlockDeltasHeader {
+                count: deltas.len() as u32,
+            };
+            $(
+                if deltas.len() <= $size {
+                    let mut msg = $name::zeroed();
+                    msg.header = header;
+                    msg.deltas[..deltas.len()].copy_from_slice(deltas);
+                    return network.send(msg, addr);
+                }
+            )*
+            panic!("{} block deltas don't fit a batch", deltas.len());
+        }
+    };
+}
+
+block_delta_buckets! {
+    MsgUpdateChunks8 => 8,
+    MsgUpdateChunks32 => 32,
+    MsgUpdateChunks128 => MAX_BLOCK_DELTAS_PER_BATCH,
+}
+
+/// reads the deltas of a received `UpdateChunks` message, None if its count runs past its end
+pub fn block_deltas(data: &[u8]) -> Option<Vec<BlockDelta>> {
+    let header_size = size_of::<BlockDeltasHeader>();
+    let header: BlockDeltasHeader =
+        bytemuck::try_pod_read_unaligned(data.get(..header_size)?).ok()?;
+    let count = header.count as usize;
+    if count > MAX_BLOCK_DELTAS_PER_BATCH {
+        return None;
+    }
+    let deltas = data.get(header_size..header_size + count * size_of::<BlockDelta>())?;
+    Some(bytemuck::pod_collect_to_vec(deltas))
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[network_message(tag = ServerMessageTag::SetPositionRequest.as_tag())]
 pub struct MsgSetPositionRequest {
+    pub position: Vec3, // feet position
+    pub rotation: [f32; 4], // where the player is looking, as a quat
+    pub teleport_id: u32, // latest one the client applied
+    pub noclip: u8,
+    _pad: [u8; 3],
+}
+
+impl MsgSetPositionRequest {
+    pub fn new(position: Vec3, rotation: Quat, teleport_id: u32, noclip: bool) -> Self {
+        Self {
+            position,
+            rotation: rotation.to_array(),
+            teleport_id,
+            noclip: noclip as u8,
+            _pad: [0; 3],
+        }
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_array(self.rotation)
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetPositionKind {
+    Correction, // a rejected move, small ones may be smoothed out
+    Teleport,   // always snapped to
+    __Count,
+}
+impl_try_from_uint!(u8 => SetPositionKind);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::SetPosition.as_tag())]
+pub struct MsgSetPosition {
+    pub position: Vec3, // feet position
+    pub teleport_id: u32,
+    pub kind: u8,
+    _pad: [u8; 3],
+}
+
+impl MsgSetPosition {
+    pub fn new(position: Vec3, teleport_id: u32, kind: SetPositionKind) -> Self {
+        Self {
+            position,
+            teleport_id,
+            kind: kind as u8,
+            _pad: [0; 3],
+        }
+    }
+
+    pub fn kind(&self) -> Option<SetPositionKind> {
+        SetPositionKind::try_from(self.kind).ok()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::EntitySpawn.as_tag())]
+pub struct MsgEntitySpawn {
+    pub id: EntityId,
+    pub kind: u8,
+    _pad: [u8; 3],
     pub position: Vec3,
+    pub rotation: [f32; 4], // quat, glam's is over-aligned for Pod
+    pub velocity: Vec3,
+    pub data: [u8; ENTITY_DATA_SIZE],
+}
+
+impl MsgEntitySpawn {
+    pub fn new(entity: &Entity) -> Self {
+        Self {
+            id: entity.id,
+            kind: entity.kind() as u8,
+            _pad: [0; 3],
+            position: entity.position,
+            rotation: entity.rotation.to_array(),
+            velocity: entity.velocity,
+            data: entity.data.to_bytes(),
+        }
+    }
+
+    pub fn to_entity(&self) -> Option<Entity> {
+        let kind = EntityKind::try_from(self.kind).ok()?;
+        Some(Entity {
+            id: self.id,
+            position: self.position,
+            rotation: Quat::from_array(self.rotation),
+            velocity: self.velocity,
+            data: EntityData::from_bytes(kind, &self.data)?,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::EntityDespawn.as_tag())]
+pub struct MsgEntityDespawn {
+    pub id: EntityId,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::EntityUpdate.as_tag())]
+pub struct MsgEntityUpdate {
+    pub id: EntityId,
+    pub position: Vec3,
+    pub rotation: [f32; 4],
+    pub velocity: Vec3,
+}
+
+impl MsgEntityUpdate {
+    pub fn new(entity: &Entity) -> Self {
+        Self {
+            id: entity.id,
+            position: entity.position,
+            rotation: entity.rotation.to_array(),
+            velocity: entity.velocity,
+        }
+    }
+
+    pub fn apply(&self, entity: &mut Entity) {
+        entity.position = self.position;
+        entity.rotation = Quat::from_array(self.rotation);
+        entity.velocity = self.velocity;
+    }
+}
+
+/// another player connected, or was already there when we did
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::PlayerJoin.as_tag())]
+pub struct MsgPlayerJoin {
+    pub player_id: u32,
+    pub name: [u8; MAX_PLAYER_NAME_LEN], // utf8, `name_len` bytes used
+    pub name_len: u8,
+    _pad: [u8; 3],
+}
+
+impl MsgPlayerJoin {
+    pub fn new(player_id: u32, name: &str) -> Self {
+        let (name, name_len) = encode_text(name);
+        Self {
+            player_id,
+            name,
+            name_len: name_len as u8,
+            _pad: [0; 3],
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        decode_text(&self.name, self.name_len as usize)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::PlayerLeave.as_tag())]
+pub struct MsgPlayerLeave {
+    pub player_id: u32,
+}
+
+pub const MAX_PLAYERS_PER_SNAPSHOT: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct PlayerState {
+    pub player_id: u32,
+    pub position: Vec3,     // feet position
+    pub rotation: [f32; 4], // quat, glam's is over-aligned for Pod
+}
+
+/// where the players near the receiver are, only the ones in range are included
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::PlayerSnapshot.as_tag())]
+pub struct MsgPlayerSnapshot {
+    pub time: u32, // server milliseconds, orders snapshots and spaces them for interpolation
+    pub count: u16,
+    _pad: [u8; 2],
+    pub states: [PlayerState; MAX_PLAYERS_PER_SNAPSHOT],
+}
+
+impl MsgPlayerSnapshot {
+    pub fn new(time: u32) -> Self {
+        Self {
+            time,
+            ..Self::zeroed()
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count as usize == MAX_PLAYERS_PER_SNAPSHOT
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn push(&mut self, player_id: usize, position: Vec3, rotation: Quat) {
+        self.states[self.count as usize] = PlayerState {
+            player_id: player_id as u32,
+            position,
+            rotation: rotation.to_array(),
+        };
+        self.count += 1;
+    }
+
+    pub fn states(&self) -> &[PlayerState] {
+        &self.states[..(self.count as usize).min(MAX_PLAYERS_PER_SNAPSHOT)]
+    }
+}
+
+pub const MAX_CHAT_LEN: usize = 256; // bytes of utf8
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatError {
+    Empty,
+    TooLong,
+    InvalidUtf8,
+    ControlCharacter,
+}
+
+/// what players may say, checked by the client before sending and by the server on receipt
+pub fn validate_chat_text(text: &str) -> Result<&str, ChatError> {
+    if text.trim().is_empty() {
+        return Err(ChatError::Empty);
+    }
+    if text.len() > MAX_CHAT_LEN {
+        return Err(ChatError::TooLong);
+    }
+    if text.chars().any(char::is_control) {
+        return Err(ChatError::ControlCharacter);
+    }
+    Ok(text)
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::ChatRequest.as_tag())]
+pub struct MsgChatRequest {
+    pub len: u16,
+    _pad: [u8; 2],
+    pub text: [u8; MAX_CHAT_LEN], // utf8, `len` bytes used
+}
+
+impl MsgChatRequest {
+    pub fn new(text: &str) -> Result<Self, ChatError> {
+        let text = validate_chat_text(text)?;
+        let (text, len) = encode_text(text);
+        Ok(Self {
+            len: len as u16,
+            _pad: [0; 2],
+            text,
+        })
+    }
+
+    pub fn text(&self) -> Result<&str, ChatError> {
+        let bytes = self
+            .text
+            .get(..self.len as usize)
+            .ok_or(ChatError::TooLong)?;
+        let text = std::str::from_utf8(bytes).map_err(|_| ChatError::InvalidUtf8)?;
+        validate_chat_text(text)
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatKind {
+    Player, // relayed from a player, `sender` is their name
+    System, // from the server itself, joins, leaves and command output
+    __Count,
+}
+impl_try_from_uint!(u8 => ChatKind);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::Chat.as_tag())]
+pub struct MsgChat {
+    pub sender_id: u32,
+    pub kind: u8,
+    pub sender_len: u8,
+    pub len: u16,
+    pub sender: [u8; MAX_PLAYER_NAME_LEN], // utf8, `sender_len` bytes used
+    pub text: [u8; MAX_CHAT_LEN],          // utf8, `len` bytes used
+}
+
+impl MsgChat {
+    pub fn player(sender_id: u32, sender: &str, text: &str) -> Self {
+        let (sender, sender_len) = encode_text(sender);
+        let (text, len) = encode_text(text);
+        Self {
+            sender_id,
+            kind: ChatKind::Player as u8,
+            sender_len: sender_len as u8,
+            len: len as u16,
+            sender,
+            text,
+        }
+    }
+
+    /// text longer than `MAX_CHAT_LEN` bytes is truncated
+    pub fn system(text: &str) -> Self {
+        let (text, len) = encode_text(text);
+        Self {
+            kind: ChatKind::System as u8,
+            len: len as u16,
+            text,
+            ..Self::zeroed()
+        }
+    }
+
+    pub fn kind(&self) -> Option<ChatKind> {
+        ChatKind::try_from(self.kind).ok()
+    }
+
+    pub fn sender(&self) -> Option<&str> {
+        decode_text(&self.sender, self.sender_len as usize)
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        decode_text(&self.text, self.len as usize)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::WorldTime.as_tag())]
+pub struct MsgWorldTime {
+    pub ticks: u64,
+    pub frozen: u8,
+    _pad: [u8; 7],
+}
+
+impl MsgWorldTime {
+    pub fn new(time: WorldTime) -> Self {
+        Self {
+            ticks: time.ticks,
+            frozen: time.frozen as u8,
+            _pad: [0; 7],
+        }
+    }
+
+    pub fn world_time(&self) -> WorldTime {
+        WorldTime {
+            ticks: self.ticks,
+            frozen: self.frozen != 0,
+        }
+    }
 }
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[network_message(tag = ServerMessageTag::Ping.as_tag())]
 pub struct MsgPing {
+    pub id: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::Pong.as_tag())]
+pub struct MsgPong {
+    pub id: u32, // echoes the ping
+}
+
+/// acknowledges one reliable message, see `reliability`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::Ack.as_tag())]
+pub struct MsgAck {
+    pub epoch: u32,
+    pub sequence: u32,
+    pub channel: u8,
+    _pad: [u8; 3],
+}
+
+impl MsgAck {
+    pub(crate) fn new(header: &ReliableHeader) -> Self {
+        Self {
+            epoch: header.epoch,
+            sequence: header.sequence,
+            channel: header.channel,
+            _pad: [0; 3],
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Quit,
+    Timeout,
+    Kicked,
+    ServerShutdown,
+    __Count,
+}
+impl_try_from_uint!(u8 => DisconnectReason);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::DisconnectRequest.as_tag())]
+pub struct MsgDisconnectRequest {
     pub byte: u8,
 }
 
-fn pop_network_msg_tag(data: &mut Vec<u8>) -> NetworkMessageTag {
-    NetworkMessageTag::from_be_bytes(
-        data.split_off(data.len() - size_of::<NetworkMessageTag>())
-            .try_into()
-            .unwrap(),
-    )
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[network_message(tag = ServerMessageTag::Disconnect.as_tag())]
+pub struct MsgDisconnect {
+    pub reason: u8,
+}
+
+impl MsgDisconnect {
+    pub fn new(reason: DisconnectReason) -> Self {
+        Self {
+            reason: reason as u8,
+        }
+    }
+
+    pub fn reason(&self) -> Option<DisconnectReason> {
+        DisconnectReason::try_from(self.reason).ok()
+    }
+}
+
+fn pop_network_msg_tag(data: &mut Vec<u8>) -> Option<NetworkMessageTag> {
+    let tag_start = data.len().checked_sub(size_of::<NetworkMessageTag>())?;
+    let tag_bytes = data.split_off(tag_start).try_into().ok()?;
+    Some(NetworkMessageTag::from_be_bytes(tag_bytes))
 }
 
-pub fn process_message(mut message: ReceivedMessage) -> ServerMessage {
-    let network_tag = pop_network_msg_tag(&mut message.data);
-    let server_tag = ServerMessageTag::try_from(network_tag).unwrap();
-    ServerMessage {
+/// strips the tag and, for reliable messages, the reliability header off a received datagram
+pub(crate) fn process_message(
+    mut message: ReceivedMessage,
+    receiver: Endpoint,
+) -> Result<(ServerMessage, Option<ReliableHeader>), DecodeError> {
+    let network_tag = pop_network_msg_tag(&mut message.data).ok_or(DecodeError::MissingTag)?;
+    let server_tag = ServerMessageTag::try_from(network_tag)
+        .map_err(|_| DecodeError::UnknownTag(network_tag))?;
+    let header = match protocol::delivery(server_tag) {
+        Delivery::Unreliable => None,
+        Delivery::Reliable(_) => Some(ReliableHeader::pop(server_tag, &mut message.data)?),
+    };
+    server_tag.validate_payload(&message.data, receiver)?;
+    let message = ServerMessage {
         tag: server_tag,
         message,
-    }
+    };
+    Ok((message, header))
 }
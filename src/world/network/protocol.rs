@@ -0,0 +1,342 @@
+use crate::world::network::chunk_codec::CHUNK_CODEC_VERSION;
+use crate::world::network::{
+    BLOCK_DELTA_BUCKETS, BlockDelta, BlockDeltasHeader, ENCODED_CHUNK_BUCKETS, EncodedChunkHeader,
+    MsgAck, MsgChat, MsgChatRequest, MsgChunkData, MsgChunkDataAck, MsgChunkDataEmpty,
+    MsgChunkDataRequest, MsgConnect, MsgConnectDeny, MsgConnectRequest, MsgDisconnect,
+    MsgDisconnectRequest, MsgEntityDespawn, MsgEntitySpawn, MsgEntityUpdate, MsgPing,
+    MsgPlayerJoin, MsgPlayerLeave, MsgPlayerSnapshot, MsgPong, MsgSetPosition,
+    MsgSetPositionRequest, MsgWorldTime, ServerMessageTag,
+};
+
+// bump for wire changes the message table can't see, like fields being reordered or changing
+// meaning without changing a message's size
+const PROTOCOL_REVISION: u32 = 1;
+
+/// peers only talk to peers with the same version, it changes whenever the message table does
+pub const PROTOCOL_VERSION: u32 = message_table_fingerprint();
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum PayloadLayout {
+    Fixed(usize),
+    // starts with the sender's protocol version, any size is let through so peers on other
+    // versions can still be told why they are rejected
+    Handshake(usize),
+    EncodedChunk, // a header and up to a bucket of codec bytes
+    BlockDeltas,  // a header and up to a bucket of deltas
+    Unsent,       // the tag exists but nothing sends it on its own
+}
+
+impl PayloadLayout {
+    /// folds the layout into `hash`, bucketed layouts along with every bucket's size
+    const fn fingerprint(&self, hash: u64) -> u64 {
+        match self {
+            PayloadLayout::Fixed(size) => fnv1a(hash, *size as u64),
+            PayloadLayout::Handshake(size) => fnv1a(hash, (1 << 32) | *size as u64),
+            PayloadLayout::EncodedChunk => {
+                let hash = fnv1a(hash, (2 << 32) | size_of::<EncodedChunkHeader>() as u64);
+                fnv1a_all(hash, ENCODED_CHUNK_BUCKETS)
+            }
+            PayloadLayout::Unsent => fnv1a(hash, 3 << 32),
+            PayloadLayout::BlockDeltas => {
+                let hash = fnv1a(hash, (4 << 32) | size_of::<BlockDeltasHeader>() as u64);
+                let hash = fnv1a(hash, size_of::<BlockDelta>() as u64);
+                fnv1a_all(hash, BLOCK_DELTA_BUCKETS)
+            }
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReliableChannel {
+    Control, // handshakes and disconnects
+    Player,  // server placements of the player, ordered
+    World,   // chunk edits, entities and players joining and leaving, ordered
+    Text,    // chat and system messages, ordered
+    __Count,
+}
+crate::impl_try_from_uint!(u8 => ReliableChannel);
+
+impl ReliableChannel {
+    /// messages on ordered channels are handed over in the order they were sent
+    pub const fn is_ordered(&self) -> bool {
+        match self {
+            ReliableChannel::Control => false,
+            ReliableChannel::Player | ReliableChannel::World | ReliableChannel::Text => true,
+            ReliableChannel::__Count => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    Unreliable,
+    Reliable(ReliableChannel), // acked and resent until acked, see `reliability`
+}
+
+impl Delivery {
+    const fn fingerprint(&self) -> u64 {
+        match self {
+            Delivery::Unreliable => 0,
+            Delivery::Reliable(channel) => 1 + *channel as u64,
+        }
+    }
+}
+
+/// the end of a connection a `NetworkHandle` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Server,
+    Client,
+}
+
+/// which end a message is sent to, anything arriving at the other end is dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    ToServer,
+    ToClient,
+    Both,
+}
+
+impl Direction {
+    pub const fn reaches(&self, endpoint: Endpoint) -> bool {
+        match (self, endpoint) {
+            (Direction::Both, _) => true,
+            (Direction::ToServer, Endpoint::Server) | (Direction::ToClient, Endpoint::Client) => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    const fn fingerprint(&self) -> u64 {
+        *self as u64
+    }
+}
+
+type MessageEntry = (ServerMessageTag, PayloadLayout, Delivery, Direction);
+
+/// every message in tag order
+const MESSAGE_TABLE: [MessageEntry; ServerMessageTag::__Count as usize] = {
+    use Delivery::*;
+    use Direction::*;
+    use PayloadLayout::*;
+    use ReliableChannel::*;
+    use ServerMessageTag::*;
+    [
+        (
+            ConnectRequest,
+            Handshake(size_of::<MsgConnectRequest>()),
+            Reliable(Control),
+            ToServer,
+        ),
+        (
+            ConnectDeny,
+            Handshake(size_of::<MsgConnectDeny>()),
+            Reliable(Control),
+            ToClient,
+        ),
+        (
+            Connect,
+            Fixed(size_of::<MsgConnect>()),
+            Reliable(Control),
+            ToClient,
+        ),
+        (
+            DisconnectRequest,
+            Fixed(size_of::<MsgDisconnectRequest>()),
+            Reliable(Control),
+            ToServer,
+        ),
+        (
+            Disconnect,
+            Fixed(size_of::<MsgDisconnect>()),
+            Reliable(Control),
+            ToClient,
+        ),
+        // lost chunks are requested again, or pushed again when not acked
+        (
+            ChunkDataRequest,
+            Fixed(size_of::<MsgChunkDataRequest>()),
+            Unreliable,
+            ToServer,
+        ),
+        (ChunkDataDeny, Unsent, Unreliable, ToClient),
+        (
+            ChunkData,
+            Fixed(size_of::<MsgChunkData>()),
+            Unreliable,
+            ToClient,
+        ),
+        (
+            ChunkDataEmpty,
+            Fixed(size_of::<MsgChunkDataEmpty>()),
+            Unreliable,
+            ToClient,
+        ),
+        (ChunkDataEncoded, EncodedChunk, Unreliable, ToClient),
+        (
+            ChunkDataAck,
+            Fixed(size_of::<MsgChunkDataAck>()),
+            Unreliable,
+            ToServer,
+        ),
+        (UpdateChunksRequest, Unsent, Unreliable, ToServer),
+        (UpdateChunksDeny, Unsent, Unreliable, ToClient),
+        (UpdateChunks, BlockDeltas, Reliable(World), ToClient),
+        (
+            SetPositionRequest,
+            Fixed(size_of::<MsgSetPositionRequest>()),
+            Unreliable,
+            ToServer,
+        ),
+        (SetPositionDeny, Unsent, Unreliable, ToClient),
+        (
+            SetPosition,
+            Fixed(size_of::<MsgSetPosition>()),
+            Reliable(Player),
+            ToClient,
+        ),
+        (
+            EntitySpawn,
+            Fixed(size_of::<MsgEntitySpawn>()),
+            Reliable(World),
+            ToClient,
+        ),
+        (
+            EntityDespawn,
+            Fixed(size_of::<MsgEntityDespawn>()),
+            Reliable(World),
+            ToClient,
+        ),
+        // ordered along with spawns and despawns, an update never arrives before its spawn
+        (
+            EntityUpdate,
+            Fixed(size_of::<MsgEntityUpdate>()),
+            Reliable(World),
+            ToClient,
+        ),
+        (
+            PlayerJoin,
+            Fixed(size_of::<MsgPlayerJoin>()),
+            Reliable(World),
+            ToClient,
+        ),
+        (
+            PlayerLeave,
+            Fixed(size_of::<MsgPlayerLeave>()),
+            Reliable(World),
+            ToClient,
+        ),
+        // a lost snapshot is made up for by the next one
+        (
+            PlayerSnapshot,
+            Fixed(size_of::<MsgPlayerSnapshot>()),
+            Unreliable,
+            ToClient,
+        ),
+        (
+            ChatRequest,
+            Fixed(size_of::<MsgChatRequest>()),
+            Reliable(Text),
+            ToServer,
+        ),
+        (Chat, Fixed(size_of::<MsgChat>()), Reliable(Text), ToClient),
+        (
+            WorldTime,
+            Fixed(size_of::<MsgWorldTime>()),
+            Unreliable,
+            ToClient,
+        ),
+        (Ping, Fixed(size_of::<MsgPing>()), Unreliable, Both),
+        (Pong, Fixed(size_of::<MsgPong>()), Unreliable, Both),
+        (Ack, Fixed(size_of::<MsgAck>()), Unreliable, Both),
+    ]
+};
+
+// compatibility checks over the message table, evaluated at compile time
+const _: () = {
+    let mut i = 0;
+    while i < MESSAGE_TABLE.len() {
+        assert!(
+            MESSAGE_TABLE[i].0 as usize == i,
+            "message table out of tag order"
+        );
+        i += 1;
+    }
+    // the handshake is how mismatched peers find out, so it can never move
+    assert!(ServerMessageTag::ConnectRequest as u8 == 0);
+    assert!(ServerMessageTag::ConnectDeny as u8 == 1);
+    assert!(std::mem::offset_of!(MsgConnectRequest, protocol_version) == 0);
+    assert!(std::mem::offset_of!(MsgConnectDeny, protocol_version) == 0);
+    // acks can't be acked
+    assert!(matches!(
+        MESSAGE_TABLE[ServerMessageTag::Ack as usize].2,
+        Delivery::Unreliable
+    ));
+};
+
+const fn fnv1a(mut hash: u64, value: u64) -> u64 {
+    let bytes = value.to_le_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+}
+
+const fn fnv1a_all(mut hash: u64, values: &[usize]) -> u64 {
+    let mut i = 0;
+    while i < values.len() {
+        hash = fnv1a(hash, values[i] as u64);
+        i += 1;
+    }
+    hash
+}
+
+const fn message_table_fingerprint() -> u32 {
+    let mut hash = fnv1a(0xcbf29ce484222325, PROTOCOL_REVISION as u64);
+    hash = fnv1a(hash, CHUNK_CODEC_VERSION as u64);
+    let mut i = 0;
+    while i < MESSAGE_TABLE.len() {
+        let (tag, layout, delivery, direction) = MESSAGE_TABLE[i];
+        hash = fnv1a(hash, tag as u64);
+        hash = layout.fingerprint(hash);
+        hash = fnv1a(hash, delivery.fingerprint());
+        hash = fnv1a(hash, direction.fingerprint());
+        i += 1;
+    }
+    (hash ^ (hash >> 32)) as u32
+}
+
+pub(crate) const fn payload_layout(tag: ServerMessageTag) -> PayloadLayout {
+    MESSAGE_TABLE[tag as usize].1
+}
+
+pub(crate) const fn delivery(tag: ServerMessageTag) -> Delivery {
+    MESSAGE_TABLE[tag as usize].2
+}
+
+pub(crate) const fn direction(tag: ServerMessageTag) -> Direction {
+    MESSAGE_TABLE[tag as usize].3
+}
+
+/// the protocol version a handshake message was sent with, readable across versions
+pub fn handshake_protocol_version(payload: &[u8]) -> Option<u32> {
+    let bytes = payload.get(..size_of::<u32>())?;
+    Some(bytemuck::pod_read_unaligned(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fails on any change to the message table, bump the expected value only along with the
+    // wire change that caused it
+    #[test]
+    fn protocol_version() {
+        assert_eq!(PROTOCOL_VERSION, 0x874de8af);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::voxer_network::{NetworkMessageConfig, NetworkMessageTag, NetworkSerializable};
+use crate::world::network::protocol::ReliableChannel;
+use crate::world::network::transport::{NetworkMessage, WireMessage};
+use crate::world::network::{DecodeError, MsgAck, ServerMessage, ServerMessageTag};
+use bytemuck::{Pod, Zeroable};
+use rustc_hash::FxHashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const INITIAL_RESEND_TIMEOUT: Duration = Duration::from_millis(200);
+const MAX_RESEND_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_SEND_ATTEMPTS: u32 = 10; // the peer is most likely gone by then
+const RECEIVE_WINDOW: u32 = 512; // messages further ahead are dropped unacked and resent later
+const RETIRED_EPOCHS: usize = 8; // remembered per peer, so late resends from them are ignored
+const CHANNEL_COUNT: usize = ReliableChannel::__Count as usize;
+
+/// appended to reliable messages, between the message and the network tag
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub(crate) struct ReliableHeader {
+    pub epoch: u32, // picked by the sender per connection, a new one starts the sequences over
+    pub sequence: u32, // per channel
+    pub channel: u8,
+}
+
+impl ReliableHeader {
+    pub fn pop(tag: ServerMessageTag, data: &mut Vec<u8>) -> Result<Self, DecodeError> {
+        let start = data
+            .len()
+            .checked_sub(size_of::<Self>())
+            .ok_or(DecodeError::Truncated {
+                tag,
+                expected: size_of::<Self>(),
+                actual: data.len(),
+            })?;
+        let header = bytemuck::pod_read_unaligned(&data[start..]);
+        data.truncate(start);
+        Ok(header)
+    }
+}
+
+/// a message as sent on a reliable channel
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub(crate) struct Reliable<M> {
+    message: M,
+    header: ReliableHeader,
+}
+
+// SAFETY: packed so there is no padding, and both fields are Pod
+unsafe impl<M: Pod> Zeroable for Reliable<M> {}
+unsafe impl<M: Pod> Pod for Reliable<M> {}
+
+impl<M: Pod + NetworkMessageConfig> NetworkMessageConfig for Reliable<M> {
+    fn tag(&self) -> NetworkMessageTag {
+        let message = self.message;
+        message.tag()
+    }
+
+    fn fragment_count(&self) -> usize {
+        let message = self.message;
+        message.fragment_count()
+    }
+}
+
+/// kept until acked, to be sent again
+struct PendingMessage {
+    message: Box<dyn WireMessage>,
+    epoch: u32, // acks only count for the epoch the message was sent in
+    next_send: Instant,
+    timeout: Duration, // doubles with every resend
+    attempts: u32,
+}
+
+#[derive(Default)]
+struct ReceiveChannel {
+    next_sequence: u32,
+    ahead: FxHashMap<u32, Option<ServerMessage>>, // received past a gap, held back if ordered
+}
+
+enum Received {
+    New,
+    Duplicate,
+    OutOfWindow,
+}
+
+impl ReceiveChannel {
+    fn receive<F>(
+        &mut self,
+        sequence: u32,
+        ordered: bool,
+        message: ServerMessage,
+        deliver: &mut F,
+    ) -> Received
+    where
+        F: FnMut(ServerMessage),
+    {
+        let offset = sequence.wrapping_sub(self.next_sequence);
+        if offset > u32::MAX / 2 || self.ahead.contains_key(&sequence) {
+            return Received::Duplicate;
+        }
+        if offset >= RECEIVE_WINDOW {
+            return Received::OutOfWindow;
+        }
+        if offset != 0 {
+            match ordered {
+                true => self.ahead.insert(sequence, Some(message)),
+                false => {
+                    deliver(message);
+                    self.ahead.insert(sequence, None)
+                }
+            };
+            return Received::New;
+        }
+        deliver(message);
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        while let Some(held) = self.ahead.remove(&self.next_sequence) {
+            if let Some(message) = held {
+                deliver(message);
+            }
+            self.next_sequence = self.next_sequence.wrapping_add(1);
+        }
+        Received::New
+    }
+}
+
+struct Peer {
+    send_epoch: u32,
+    next_sequence: [u32; CHANNEL_COUNT],
+    receive_epoch: Option<u32>,
+    retired_epochs: Vec<u32>, // ones the peer started over from, oldest first
+    receive: [ReceiveChannel; CHANNEL_COUNT],
+}
+
+impl Peer {
+    fn new(send_epoch: u32) -> Self {
+        Self {
+            send_epoch,
+            next_sequence: [0; CHANNEL_COUNT],
+            receive_epoch: None,
+            retired_epochs: Vec::new(),
+            receive: Default::default(),
+        }
+    }
+}
+
+/// sequencing, acks and resends of reliable messages, for every peer of a `NetworkHandle`
+pub(crate) struct Reliability {
+    peers: FxHashMap<SocketAddr, Peer>,
+    pending: FxHashMap<(SocketAddr, u8, u32), PendingMessage>, // by peer, channel and sequence
+    last_epoch: u32,
+}
+
+impl Reliability {
+    pub fn new() -> Self {
+        // epochs only need to differ from the ones this process or a previous one gave a peer
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        Self {
+            peers: FxHashMap::default(),
+            pending: FxHashMap::default(),
+            last_epoch: nanos,
+        }
+    }
+
+    fn peer(&mut self, addr: SocketAddr) -> &mut Peer {
+        self.peers.entry(addr).or_insert_with(|| {
+            self.last_epoch = self.last_epoch.wrapping_add(1);
+            Peer::new(self.last_epoch)
+        })
+    }
+
+    /// starts a new connection with `addr`, what we send starts over under a new epoch and
+    /// anything unacked is dropped, what we receive follows the epochs the peer sends
+    pub fn reset_peer(&mut self, addr: SocketAddr) {
+        self.last_epoch = self.last_epoch.wrapping_add(1);
+        let epoch = self.last_epoch;
+        let peer = self.peer(addr);
+        peer.send_epoch = epoch;
+        peer.next_sequence = [0; CHANNEL_COUNT];
+        self.pending
+            .retain(|(pending_addr, _, _), _| *pending_addr != addr);
+    }
+
+    pub fn prepare<M: NetworkMessage>(
+        &mut self,
+        message: M,
+        channel: ReliableChannel,
+        addr: SocketAddr,
+        now: Instant,
+    ) -> Reliable<M>
+    where
+        Reliable<M>: NetworkSerializable,
+    {
+        let peer = self.peer(addr);
+        let sequence = peer.next_sequence[channel as usize];
+        peer.next_sequence[channel as usize] = sequence.wrapping_add(1);
+        let reliable = Reliable {
+            message,
+            header: ReliableHeader {
+                epoch: peer.send_epoch,
+                sequence,
+                channel: channel as u8,
+            },
+        };
+        let pending = PendingMessage {
+            message: Box::new(reliable),
+            epoch: peer.send_epoch,
+            next_send: now + INITIAL_RESEND_TIMEOUT,
+            timeout: INITIAL_RESEND_TIMEOUT,
+            attempts: 1,
+        };
+        self.pending
+            .insert((addr, channel as u8, sequence), pending);
+        reliable
+    }
+
+    /// forgets `addr` once it is no longer connected, messages still pending to it are resent
+    /// until acked or given up on
+    pub fn forget_peer(&mut self, addr: SocketAddr) {
+        self.peers.remove(&addr);
+    }
+
+    pub fn on_ack(&mut self, addr: SocketAddr, ack: &MsgAck) {
+        let key = (addr, ack.channel, ack.sequence);
+        if self
+            .pending
+            .get(&key)
+            .is_some_and(|pending| pending.epoch == ack.epoch)
+        {
+            self.pending.remove(&key);
+        }
+    }
+
+    /// hands `message` to `deliver` unless it is a duplicate, returns the ack to send back
+    pub fn receive<F>(
+        &mut self,
+        addr: SocketAddr,
+        header: ReliableHeader,
+        message: ServerMessage,
+        mut deliver: F,
+    ) -> Option<MsgAck>
+    where
+        F: FnMut(ServerMessage),
+    {
+        let channel = ReliableChannel::try_from(header.channel).ok()?;
+        let peer = self.peer(addr);
+        if peer.receive_epoch != Some(header.epoch) {
+            // epochs are random, so older ones are told apart by having been seen before
+            if peer.retired_epochs.contains(&header.epoch) {
+                return None; // the peer dropped it when it started over, no need to ack
+            }
+            if let Some(retired) = peer.receive_epoch.replace(header.epoch) {
+                if peer.retired_epochs.len() == RETIRED_EPOCHS {
+                    peer.retired_epochs.remove(0);
+                }
+                peer.retired_epochs.push(retired);
+            }
+            peer.receive = Default::default();
+        }
+        let receive_channel = &mut peer.receive[channel as usize];
+        let ordered = channel.is_ordered();
+        match receive_channel.receive(header.sequence, ordered, message, &mut deliver) {
+            Received::New | Received::Duplicate => Some(MsgAck::new(&header)),
+            Received::OutOfWindow => None,
+        }
+    }
+
+    /// sends again whatever wasn't acked in time, giving up on messages after too many attempts.
+    /// returns the peers an ordered channel was given up on for, nothing sent on it after the
+    /// gap would ever be delivered so they have to be disconnected
+    pub fn poll_resends<F>(&mut self, now: Instant, mut send: F) -> Vec<SocketAddr>
+    where
+        F: FnMut(SocketAddr, &dyn WireMessage),
+    {
+        let mut broken = Vec::new();
+        self.pending.retain(|(addr, channel, sequence), pending| {
+            if now < pending.next_send {
+                return true;
+            }
+            if pending.attempts >= MAX_SEND_ATTEMPTS {
+                println!("gave up on reliable message {channel}:{sequence} to {addr}");
+                let ordered = ReliableChannel::try_from(*channel).is_ok_and(|c| c.is_ordered());
+                if ordered && !broken.contains(addr) {
+                    broken.push(*addr);
+                }
+                return false;
+            }
+            send(*addr, pending.message.as_ref());
+            pending.attempts += 1;
+            pending.timeout = (pending.timeout * 2).min(MAX_RESEND_TIMEOUT);
+            pending.next_send = now + pending.timeout;
+            true
+        });
+        // the rest would only pile up behind the gap
+        self.pending
+            .retain(|(addr, _, _), _| !broken.contains(addr));
+        broken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::network::{DisconnectReason, MsgDisconnect, ReceivedMessage};
+
+    fn addr() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 4000))
+    }
+
+    /// a message told apart by its data, which is `id`
+    fn message(id: u8) -> ServerMessage {
+        ServerMessage {
+            tag: ServerMessageTag::Ping,
+            message: ReceivedMessage {
+                data: vec![id],
+                src: addr(),
+            },
+        }
+    }
+
+    fn header(epoch: u32, sequence: u32, channel: ReliableChannel) -> ReliableHeader {
+        ReliableHeader {
+            epoch,
+            sequence,
+            channel: channel as u8,
+        }
+    }
+
+    /// receives `sequence` on `channel`, returns whether it was acked and the ids delivered
+    fn receive(
+        reliability: &mut Reliability,
+        epoch: u32,
+        sequence: u32,
+        channel: ReliableChannel,
+    ) -> (bool, Vec<u8>) {
+        let mut delivered = Vec::new();
+        let header = header(epoch, sequence, channel);
+        let ack = reliability.receive(addr(), header, message(sequence as u8), |msg| {
+            delivered.push(msg.message.data[0]);
+        });
+        (ack.is_some(), delivered)
+    }
+
+    #[test]
+    fn ordered_channel_holds_messages_back_until_the_gap_fills() {
+        let mut reliability = Reliability::new();
+        let channel = ReliableChannel::World;
+        assert_eq!(receive(&mut reliability, 1, 2, channel), (true, vec![]));
+        assert_eq!(receive(&mut reliability, 1, 1, channel), (true, vec![]));
+        assert_eq!(
+            receive(&mut reliability, 1, 0, channel),
+            (true, vec![0, 1, 2])
+        );
+        assert_eq!(receive(&mut reliability, 1, 3, channel), (true, vec![3]));
+    }
+
+    #[test]
+    fn unordered_channel_delivers_on_arrival() {
+        let mut reliability = Reliability::new();
+        let channel = ReliableChannel::Control;
+        assert_eq!(receive(&mut reliability, 1, 2, channel), (true, vec![2]));
+        assert_eq!(receive(&mut reliability, 1, 0, channel), (true, vec![0]));
+        assert_eq!(receive(&mut reliability, 1, 1, channel), (true, vec![1]));
+    }
+
+    #[test]
+    fn duplicates_are_acked_but_delivered_once() {
+        let mut reliability = Reliability::new();
+        for channel in [ReliableChannel::Control, ReliableChannel::World] {
+            // once delivered, and once held back or delivered ahead of a gap
+            assert_eq!(receive(&mut reliability, 1, 0, channel), (true, vec![0]));
+            assert_eq!(receive(&mut reliability, 1, 0, channel), (true, vec![]));
+            let (acked, _) = receive(&mut reliability, 1, 2, channel);
+            assert!(acked);
+            assert_eq!(receive(&mut reliability, 1, 2, channel), (true, vec![]));
+        }
+    }
+
+    #[test]
+    fn late_resends_from_retired_epochs_are_dropped() {
+        let mut reliability = Reliability::new();
+        let channel = ReliableChannel::World;
+        assert_eq!(receive(&mut reliability, 1, 0, channel), (true, vec![0]));
+        // the peer started over, anything still in flight from before is stale
+        assert_eq!(receive(&mut reliability, 2, 0, channel), (true, vec![0]));
+        assert_eq!(receive(&mut reliability, 1, 1, channel), (false, vec![]));
+        assert_eq!(receive(&mut reliability, 2, 1, channel), (true, vec![1]));
+    }
+
+    #[test]
+    fn giving_up_on_an_ordered_channel_reports_the_peer() {
+        let mut reliability = Reliability::new();
+        let mut now = Instant::now();
+        let msg = MsgDisconnect::new(DisconnectReason::Timeout);
+        reliability.prepare(msg, ReliableChannel::Control, addr(), now);
+        reliability.prepare(msg, ReliableChannel::World, addr(), now);
+
+        let mut resent = 0;
+        for _ in 1..MAX_SEND_ATTEMPTS {
+            now += MAX_RESEND_TIMEOUT;
+            let broken = reliability.poll_resends(now, |_, _| resent += 1);
+            assert!(broken.is_empty());
+        }
+        assert_eq!(resent, 2 * (MAX_SEND_ATTEMPTS - 1));
+
+        now += MAX_RESEND_TIMEOUT;
+        assert_eq!(reliability.poll_resends(now, |_, _| {}), [addr()]);
+        assert!(reliability.pending.is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::compute::rng::SplitMix64;
+use crate::voxer_network::NetworkingError;
+use crate::world::network::{ReceivedMessage, Transport, WireMessage};
+use crossbeam::channel;
+use parking_lot::Mutex;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const MIN_REORDER_DELAY: Duration = Duration::from_millis(10);
+const MAX_LINK_BACKLOG: Duration = Duration::from_secs(1); // a full router queue drops the rest
+
+/// what a `SimulatedTransport` does to the datagrams it sends, the default is a perfect link
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetworkConditions {
+    pub latency: Duration,
+    pub jitter: Duration, // up to this much is added to the latency, at random
+    pub loss: f64,
+    pub duplicate: f64,
+    pub reorder: f64, // chance a datagram is held back long enough for later ones to pass it
+    pub bandwidth: Option<usize>, // bytes per second
+    pub seed: u64,
+}
+
+impl NetworkConditions {
+    /// parses options like `latency=80,jitter=20,loss=0.05,bandwidth=262144,seed=7`, also
+    /// `duplicate` and `reorder`. durations are in milliseconds, bandwidth in bytes per second
+    /// and anything left out is a perfect link
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut conditions = Self::default();
+        for option in spec.split(',').filter(|option| !option.is_empty()) {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{option}'"))?;
+            let invalid = || format!("invalid value for {key}: '{value}'");
+            match key {
+                "latency" => conditions.latency = parse_millis(value).ok_or_else(invalid)?,
+                "jitter" => conditions.jitter = parse_millis(value).ok_or_else(invalid)?,
+                "loss" => conditions.loss = parse_chance(value).ok_or_else(invalid)?,
+                "duplicate" => conditions.duplicate = parse_chance(value).ok_or_else(invalid)?,
+                "reorder" => conditions.reorder = parse_chance(value).ok_or_else(invalid)?,
+                "bandwidth" => {
+                    let bandwidth = value.parse().ok().filter(|&b: &usize| b > 0);
+                    conditions.bandwidth = Some(bandwidth.ok_or_else(invalid)?);
+                }
+                "seed" => conditions.seed = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown network condition '{key}'")),
+            }
+        }
+        Ok(conditions)
+    }
+
+    /// the same conditions with their own random sequence, for the other end of a link
+    pub fn reseeded(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+}
+
+fn parse_millis(value: &str) -> Option<Duration> {
+    value.parse().ok().map(Duration::from_millis)
+}
+
+fn parse_chance(value: &str) -> Option<f64> {
+    value.parse().ok().filter(|p| (0.0..=1.0).contains(p))
+}
+
+struct Delayed {
+    deliver_at: Instant,
+    order: u64, // keeps datagrams due at the same time in the order they were sent
+    addr: SocketAddr,
+    message: Box<dyn WireMessage>,
+}
+
+impl Delayed {
+    fn key(&self) -> (Instant, u64) {
+        (self.deliver_at, self.order)
+    }
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    // reversed, the heap pops the earliest first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+struct SimulatorState {
+    rng: SplitMix64,
+    link_free_at: Instant, // when everything sent so far is through the bandwidth cap
+    next_order: u64,
+}
+
+/// wraps another transport and sends through a simulated link, for testing under bad networks.
+/// given the same seed and the same order of sends, the same datagrams are lost, duplicated
+/// and delayed
+pub struct SimulatedTransport {
+    inner: Box<dyn Transport>,
+    conditions: NetworkConditions,
+    state: Arc<Mutex<SimulatorState>>,
+    delayed: channel::Sender<Delayed>,
+}
+
+impl SimulatedTransport {
+    pub fn new(inner: Box<dyn Transport>, conditions: NetworkConditions) -> Self {
+        let (delayed, receiver) = channel::unbounded();
+        let link = inner.clone_handle();
+        std::thread::spawn(move || Self::deliver_loop(link, receiver));
+        let state = SimulatorState {
+            rng: SplitMix64::new(conditions.seed),
+            link_free_at: Instant::now(),
+            next_order: 0,
+        };
+        Self {
+            inner,
+            conditions,
+            state: Arc::new(Mutex::new(state)),
+            delayed,
+        }
+    }
+
+    /// hands datagrams to the wrapped transport once they are due, until every handle is dropped
+    fn deliver_loop(link: Box<dyn Transport>, receiver: channel::Receiver<Delayed>) {
+        let mut queue = BinaryHeap::new();
+        loop {
+            let received = match queue.peek() {
+                Some(next) => receiver.recv_deadline(next.deliver_at),
+                None => receiver
+                    .recv()
+                    .map_err(|_| channel::RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(delayed) => queue.push(delayed),
+                Err(channel::RecvTimeoutError::Timeout) => {}
+                Err(channel::RecvTimeoutError::Disconnected) => return,
+            }
+            let now = Instant::now();
+            while queue.peek().is_some_and(|next| next.deliver_at <= now) {
+                let due = queue.pop().unwrap();
+                // the simulated link is as unreliable as the real one, errors are dropped too
+                let _ = link.send_to(due.message.as_ref(), due.addr);
+            }
+        }
+    }
+}
+
+impl Transport for SimulatedTransport {
+    fn send_to(&self, message: &dyn WireMessage, addr: SocketAddr) -> Result<(), NetworkingError> {
+        let conditions = &self.conditions;
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        if state.rng.chance(conditions.loss) {
+            return Ok(());
+        }
+        let copies = match state.rng.chance(conditions.duplicate) {
+            true => 2,
+            false => 1,
+        };
+        for _ in 0..copies {
+            let mut delay = conditions.latency + conditions.jitter.mul_f64(state.rng.next_f64());
+            if state.rng.chance(conditions.reorder) {
+                delay += conditions.latency.max(MIN_REORDER_DELAY);
+            }
+            if let Some(bandwidth) = conditions.bandwidth {
+                let start = state.link_free_at.max(now);
+                if start - now > MAX_LINK_BACKLOG {
+                    continue;
+                }
+                let transmit = Duration::from_secs_f64(message.size() as f64 / bandwidth as f64);
+                state.link_free_at = start + transmit;
+                delay += state.link_free_at - now;
+            }
+            let order = state.next_order;
+            state.next_order += 1;
+            let delayed = Delayed {
+                deliver_at: now + delay,
+                order,
+                addr,
+                message: message.clone_boxed(),
+            };
+            let _ = self.delayed.send(delayed);
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Option<ReceivedMessage> {
+        self.inner.recv()
+    }
+
+    fn clone_handle(&self) -> Box<dyn Transport> {
+        Box::new(Self {
+            inner: self.inner.clone_handle(),
+            conditions: self.conditions,
+            state: self.state.clone(),
+            delayed: self.delayed.clone(),
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+}
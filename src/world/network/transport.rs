@@ -0,0 +1,214 @@
+use crate::voxer_network::{
+    NetworkMessageConfig, NetworkSerializable, NetworkingError, UdpChannel,
+};
+use crate::world::network::ReceivedMessage;
+use bytemuck::Pod;
+use crossbeam::channel;
+use crossbeam::channel::RecvTimeoutError;
+use parking_lot::Mutex;
+use rustc_hash::FxHashMap;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+const LOOPBACK_RECV_TIMEOUT: Duration = Duration::from_millis(100);
+const LOOPBACK_FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+pub(crate) trait NetworkMessage:
+    Pod + NetworkMessageConfig + NetworkSerializable + Send
+{
+}
+impl<M: Pod + NetworkMessageConfig + NetworkSerializable + Send> NetworkMessage for M {}
+
+/// a message any transport can send
+pub trait WireMessage: Send {
+    fn boxed(&self) -> Box<dyn NetworkSerializable>;
+    /// the message's bytes followed by its tag, the way `process_message` expects them
+    fn encode(&self) -> Vec<u8>;
+    /// the message's size without its tag
+    fn size(&self) -> usize;
+    fn clone_boxed(&self) -> Box<dyn WireMessage>;
+}
+
+impl<M: NetworkMessage> WireMessage for M {
+    fn boxed(&self) -> Box<dyn NetworkSerializable> {
+        Box::new(*self)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = bytemuck::bytes_of(self).to_vec();
+        bytes.extend_from_slice(&self.tag().to_be_bytes());
+        bytes
+    }
+
+    fn size(&self) -> usize {
+        size_of::<M>()
+    }
+
+    fn clone_boxed(&self) -> Box<dyn WireMessage> {
+        Box::new(*self)
+    }
+}
+
+/// moves datagrams between peers for a `NetworkHandle`
+pub trait Transport: Send {
+    fn send_to(&self, message: &dyn WireMessage, addr: SocketAddr) -> Result<(), NetworkingError>;
+    fn recv(&mut self) -> Option<ReceivedMessage>;
+    /// another handle to the same endpoint, for the receiving thread
+    fn clone_handle(&self) -> Box<dyn Transport>;
+    /// nothing will be received anymore, the receiving thread stops
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+pub struct UdpTransport {
+    channel: UdpChannel,
+}
+
+impl UdpTransport {
+    pub fn bind<A: ToSocketAddrs>(addr: A, buffer_size: usize) -> Self {
+        Self {
+            channel: UdpChannel::bind(addr, buffer_size),
+        }
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send_to(&self, message: &dyn WireMessage, addr: SocketAddr) -> Result<(), NetworkingError> {
+        self.channel.send_to(message.boxed(), &addr)
+    }
+
+    fn recv(&mut self) -> Option<ReceivedMessage> {
+        let message = self.channel.recv_single()?;
+        Some(ReceivedMessage {
+            data: message.data,
+            src: message.src,
+        })
+    }
+
+    fn clone_handle(&self) -> Box<dyn Transport> {
+        Box::new(Self {
+            channel: self.channel.clone_handle(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct LoopbackEndpoints {
+    senders: FxHashMap<SocketAddr, channel::Sender<ReceivedMessage>>,
+    next_port: u16,
+}
+
+/// an in-process network, endpoints bound to it only reach each other
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork {
+    endpoints: Arc<Mutex<LoopbackEndpoints>>,
+}
+
+impl LoopbackNetwork {
+    /// port 0 picks a free one, like binding a socket would
+    pub fn bind(&self, mut addr: SocketAddr) -> LoopbackTransport {
+        let mut endpoints = self.endpoints.lock();
+        if addr.port() == 0 {
+            loop {
+                let port = endpoints.next_port.max(LOOPBACK_FIRST_EPHEMERAL_PORT);
+                endpoints.next_port = port.checked_add(1).unwrap_or(LOOPBACK_FIRST_EPHEMERAL_PORT);
+                addr.set_port(port);
+                if !endpoints.senders.contains_key(&addr) {
+                    break;
+                }
+            }
+        }
+        let (sender, receiver) = channel::unbounded();
+        let previous = endpoints.senders.insert(addr, sender);
+        assert!(
+            previous.is_none(),
+            "loopback address {addr} is already bound"
+        );
+        LoopbackTransport {
+            addr,
+            network: self.clone(),
+            receiver,
+            owner: true,
+            closed: false,
+        }
+    }
+}
+
+pub struct LoopbackTransport {
+    addr: SocketAddr,
+    network: LoopbackNetwork,
+    receiver: channel::Receiver<ReceivedMessage>,
+    owner: bool,  // the one `bind` returned, dropping it unbinds the address
+    closed: bool, // unbound, so nothing can be sent to it anymore
+}
+
+impl Transport for LoopbackTransport {
+    /// like udp, messages to nobody are dropped
+    fn send_to(&self, message: &dyn WireMessage, addr: SocketAddr) -> Result<(), NetworkingError> {
+        let endpoints = self.network.endpoints.lock();
+        if let Some(sender) = endpoints.senders.get(&addr) {
+            let received = ReceivedMessage {
+                data: message.encode(),
+                src: self.addr,
+            };
+            let _ = sender.send(received);
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Option<ReceivedMessage> {
+        match self.receiver.recv_timeout(LOOPBACK_RECV_TIMEOUT) {
+            Ok(message) => Some(message),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+
+    fn clone_handle(&self) -> Box<dyn Transport> {
+        Box::new(Self {
+            addr: self.addr,
+            network: self.network.clone(),
+            receiver: self.receiver.clone(),
+            owner: false,
+            closed: self.closed,
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+impl Drop for LoopbackTransport {
+    /// the sender goes with the entry, which disconnects the receivers of every other handle
+    fn drop(&mut self) {
+        if self.owner {
+            self.network.endpoints.lock().senders.remove(&self.addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropping_a_transport_unbinds_it() {
+        let network = LoopbackNetwork::default();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 3100));
+        let transport = network.bind(addr);
+        let mut receiving = transport.clone_handle();
+        assert!(!receiving.is_closed());
+
+        drop(transport);
+        assert!(!network.endpoints.lock().senders.contains_key(&addr));
+        assert!(receiving.recv().is_none());
+        assert!(receiving.is_closed());
+        network.bind(addr); // free to be bound again
+    }
+}
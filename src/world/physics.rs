@@ -0,0 +1,408 @@
+use crate::compute::geo::{AABB, IVec3Iter, voxel_to_chunk_pos, voxel_to_local_pos};
+use crate::world::server::block::VoxelBlock;
+use crate::world::server::chunk::VoxelChunk;
+use crate::world::server::World;
+use glam::{IVec3, Vec3};
+use rustc_hash::FxHashMap;
+
+pub const PHYSICS_TICK_RATE: u32 = 60;
+pub const PLAYER_HALF_EXTENTS: Vec3 = Vec3::new(0.3, 0.9, 0.3);
+pub const PLAYER_EYE_HEIGHT: f32 = 1.62;
+
+// keeps boxes from counting as overlapping when they only share a face
+const COLLISION_EPSILON: f32 = 1e-4;
+
+pub trait VoxelCollider {
+    fn block_at(&self, voxel: IVec3) -> Option<VoxelBlock>;
+
+    /// unloaded voxels are treated as solid so bodies don't fall through missing terrain
+    fn is_solid(&self, voxel: IVec3) -> bool {
+        self.block_at(voxel).map_or(true, |b| !b.is_transparent())
+    }
+}
+
+impl VoxelCollider for FxHashMap<IVec3, VoxelChunk> {
+    fn block_at(&self, voxel: IVec3) -> Option<VoxelBlock> {
+        self.get(&voxel_to_chunk_pos(voxel))
+            .map(|chunk| chunk.block(voxel_to_local_pos(voxel)))
+    }
+}
+
+impl VoxelCollider for dyn World + '_ {
+    fn block_at(&self, voxel: IVec3) -> Option<VoxelBlock> {
+        self.chunk(voxel_to_chunk_pos(voxel))
+            .map(|chunk| chunk.block(voxel_to_local_pos(voxel)))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicsConfig {
+    pub gravity: f32,
+    pub terminal_velocity: f32,
+    pub jump_velocity: f32,
+    pub walk_speed: f32,
+    pub sprint_multiplier: f32,
+    pub fly_speed: f32,
+    pub fly_sprint_multiplier: f32,
+    pub step_height: f32,
+}
+
+impl PhysicsConfig {
+    /// fastest a body following this config can legitimately move on x and z, in any mode
+    pub fn max_horizontal_speed(&self) -> f32 {
+        let walk = self.walk_speed * self.sprint_multiplier;
+        walk.max(self.max_fly_speed())
+    }
+
+    /// fastest a body following this config can legitimately move on y, falling or flying
+    pub fn max_vertical_speed(&self) -> f32 {
+        self.terminal_velocity.max(self.max_fly_speed())
+    }
+
+    fn max_fly_speed(&self) -> f32 {
+        self.fly_speed * self.fly_sprint_multiplier
+    }
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            gravity: 28.0,
+            terminal_velocity: 60.0,
+            jump_velocity: 9.0,
+            walk_speed: 4.5,
+            sprint_multiplier: 1.5,
+            fly_speed: 10.0,
+            fly_sprint_multiplier: 7.0,
+            step_height: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementMode {
+    Walk,
+    Fly, // noclip
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MovementInput {
+    pub wish_dir: Vec3, // world space, y is only used while flying
+    pub jump: bool,
+    pub sprint: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerBody {
+    pub position: Vec3, // center of the feet
+    pub velocity: Vec3,
+    pub on_ground: bool,
+    pub mode: MovementMode,
+}
+
+impl PlayerBody {
+    pub fn new(position: Vec3, mode: MovementMode) -> Self {
+        Self {
+            position,
+            velocity: Vec3::ZERO,
+            on_ground: false,
+            mode,
+        }
+    }
+
+    pub fn aabb_at(position: Vec3) -> AABB {
+        let center = position + Vec3::Y * PLAYER_HALF_EXTENTS.y;
+        AABB::new(center - PLAYER_HALF_EXTENTS, center + PLAYER_HALF_EXTENTS)
+    }
+
+    pub fn aabb(&self) -> AABB {
+        Self::aabb_at(self.position)
+    }
+
+    pub fn eye_position(&self) -> Vec3 {
+        self.position + Vec3::Y * PLAYER_EYE_HEIGHT
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            MovementMode::Walk => MovementMode::Fly,
+            MovementMode::Fly => MovementMode::Walk,
+        };
+        self.velocity = Vec3::ZERO;
+        self.on_ground = false;
+    }
+
+    pub fn step<C: VoxelCollider + ?Sized>(
+        &mut self,
+        input: &MovementInput,
+        config: &PhysicsConfig,
+        dt: f32,
+        world: &C,
+    ) {
+        match self.mode {
+            MovementMode::Fly => self.step_fly(input, config, dt),
+            MovementMode::Walk => self.step_walk(input, config, dt, world),
+        }
+    }
+
+    fn step_fly(&mut self, input: &MovementInput, config: &PhysicsConfig, dt: f32) {
+        let sprint_mul = if input.sprint { config.fly_sprint_multiplier } else { 1.0 };
+        self.velocity = input.wish_dir * config.fly_speed * sprint_mul;
+        self.position += self.velocity * dt;
+        self.on_ground = false;
+    }
+
+    fn step_walk<C: VoxelCollider + ?Sized>(
+        &mut self,
+        input: &MovementInput,
+        config: &PhysicsConfig,
+        dt: f32,
+        world: &C,
+    ) {
+        let sprint_mul = if input.sprint { config.sprint_multiplier } else { 1.0 };
+        let wish = Vec3::new(input.wish_dir.x, 0.0, input.wish_dir.z).normalize_or_zero();
+        self.velocity.x = wish.x * config.walk_speed * sprint_mul;
+        self.velocity.z = wish.z * config.walk_speed * sprint_mul;
+
+        if input.jump && self.on_ground {
+            self.velocity.y = config.jump_velocity;
+        }
+        self.velocity.y = (self.velocity.y - config.gravity * dt).max(-config.terminal_velocity);
+
+        let delta = self.velocity * dt;
+        let moved_y = self.move_axis(1, delta.y, world);
+        if moved_y != delta.y {
+            self.on_ground = delta.y < 0.0;
+            self.velocity.y = 0.0;
+        } else {
+            self.on_ground = false;
+        }
+
+        let horizontal_start = self.position;
+        let moved_x = self.move_axis(0, delta.x, world);
+        let moved_z = self.move_axis(2, delta.z, world);
+        let blocked = moved_x != delta.x || moved_z != delta.z;
+        if blocked && self.on_ground && config.step_height > 0.0 {
+            self.try_step_up(horizontal_start, delta, config.step_height, world);
+        }
+    }
+
+    /// retries a blocked horizontal move from `step_height` higher, keeping it if it got further
+    fn try_step_up<C: VoxelCollider + ?Sized>(
+        &mut self,
+        start: Vec3,
+        delta: Vec3,
+        step_height: f32,
+        world: &C,
+    ) {
+        let blocked_position = self.position;
+        self.position = start;
+        let raised = self.move_axis(1, step_height, world);
+        self.move_axis(0, delta.x, world);
+        self.move_axis(2, delta.z, world);
+        self.move_axis(1, -raised, world);
+
+        let stepped = (self.position - start).with_y(0.0).length_squared();
+        let blocked = (blocked_position - start).with_y(0.0).length_squared();
+        if stepped <= blocked {
+            self.position = blocked_position;
+        }
+    }
+
+    /// moves by `delta` one axis at a time in the given order, colliding the way `step` does
+    pub fn sweep<C: VoxelCollider + ?Sized>(&mut self, delta: Vec3, axes: [usize; 3], world: &C) {
+        for axis in axes {
+            self.move_axis(axis, delta[axis], world);
+        }
+    }
+
+    /// moves along a single axis up to `delta`, stopping at the first solid voxel, returns the distance moved
+    fn move_axis<C: VoxelCollider + ?Sized>(&mut self, axis: usize, delta: f32, world: &C) -> f32 {
+        if delta == 0.0 {
+            return 0.0;
+        }
+        let aabb = self.aabb();
+        let mut swept = aabb;
+        match delta > 0.0 {
+            true => swept.max[axis] += delta,
+            false => swept.min[axis] += delta,
+        }
+
+        let mut allowed = delta;
+        for voxel in overlapping_voxels(swept) {
+            if !world.is_solid(voxel) {
+                continue;
+            }
+            let voxel_min = voxel.as_vec3();
+            let voxel_max = voxel_min + Vec3::ONE;
+            allowed = match delta > 0.0 {
+                true => allowed.min(voxel_min[axis] - aabb.max[axis]),
+                false => allowed.max(voxel_max[axis] - aabb.min[axis]),
+            };
+        }
+        // never move backwards if we started slightly inside a voxel
+        let allowed = match delta > 0.0 {
+            true => allowed.max(0.0),
+            false => allowed.min(0.0),
+        };
+        self.position[axis] += allowed;
+        allowed
+    }
+}
+
+/// whether a player standing at `position` would be inside a known solid voxel
+pub fn overlaps_solid<C: VoxelCollider + ?Sized>(position: Vec3, world: &C) -> bool {
+    overlapping_voxels(PlayerBody::aabb_at(position)).any(|voxel| {
+        world
+            .block_at(voxel)
+            .is_some_and(|block| !block.is_transparent())
+    })
+}
+
+fn overlapping_voxels(aabb: AABB) -> IVec3Iter {
+    let min = (aabb.min + COLLISION_EPSILON).floor().as_ivec3();
+    let max = (aabb.max - COLLISION_EPSILON).floor().as_ivec3() + IVec3::ONE;
+    IVec3Iter::new(min.x..max.x, min.y..max.y, min.z..max.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hash::FxHashSet;
+
+    const DT: f32 = 1.0 / PHYSICS_TICK_RATE as f32;
+    const STONE: VoxelBlock = VoxelBlock { value: 1 << 15 };
+
+    /// solid voxels in an otherwise empty world that is loaded everywhere
+    #[derive(Default)]
+    struct Voxels(FxHashSet<IVec3>);
+
+    impl Voxels {
+        /// a floor whose top is at y 0
+        fn floor() -> Self {
+            let mut voxels = Self::default();
+            voxels.fill(IVec3::new(-8, -1, -8), IVec3::new(8, 0, 8));
+            voxels
+        }
+
+        fn fill(&mut self, min: IVec3, max: IVec3) {
+            self.0
+                .extend(IVec3Iter::new(min.x..max.x, min.y..max.y, min.z..max.z));
+        }
+    }
+
+    impl VoxelCollider for Voxels {
+        fn block_at(&self, voxel: IVec3) -> Option<VoxelBlock> {
+            match self.0.contains(&voxel) {
+                true => Some(STONE),
+                false => Some(VoxelBlock::EMPTY),
+            }
+        }
+    }
+
+    fn walk_east() -> MovementInput {
+        MovementInput {
+            wish_dir: Vec3::X,
+            ..MovementInput::default()
+        }
+    }
+
+    fn run(body: &mut PlayerBody, input: MovementInput, ticks: usize, world: &Voxels) {
+        let config = PhysicsConfig::default();
+        for _ in 0..ticks {
+            body.step(&input, &config, DT, world);
+        }
+    }
+
+    #[test]
+    fn falls_onto_ground() {
+        let world = Voxels::floor();
+        let mut body = PlayerBody::new(Vec3::new(0.5, 5.0, 0.5), MovementMode::Walk);
+        run(&mut body, MovementInput::default(), 120, &world);
+        assert!(body.on_ground);
+        assert!(
+            body.position.y.abs() < 1e-3,
+            "landed at {}",
+            body.position.y
+        );
+        assert_eq!(body.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn stopped_by_wall() {
+        let mut world = Voxels::floor();
+        world.fill(IVec3::new(2, 0, -8), IVec3::new(3, 3, 8));
+        let mut body = PlayerBody::new(Vec3::new(0.5, 0.0, 0.5), MovementMode::Walk);
+        run(&mut body, walk_east(), 60, &world);
+        let stop = 2.0 - PLAYER_HALF_EXTENTS.x;
+        assert!(
+            (body.position.x - stop).abs() < 1e-3,
+            "stopped at {}",
+            body.position.x
+        );
+        assert!(body.position.y.abs() < 1e-3);
+    }
+
+    #[test]
+    fn steps_up_one_block() {
+        let mut world = Voxels::floor();
+        world.fill(IVec3::new(2, 0, -8), IVec3::new(8, 1, 8));
+        let mut body = PlayerBody::new(Vec3::new(0.5, 0.0, 0.5), MovementMode::Walk);
+        run(&mut body, walk_east(), 40, &world);
+        assert!(
+            (body.position.y - 1.0).abs() < 1e-3,
+            "at height {}",
+            body.position.y
+        );
+        assert!(body.position.x > 2.0 + PLAYER_HALF_EXTENTS.x);
+        assert!(body.on_ground);
+    }
+
+    #[test]
+    fn no_double_jump() {
+        let world = Voxels::floor();
+        let config = PhysicsConfig::default();
+        let mut body = PlayerBody::new(Vec3::new(0.5, 0.0, 0.5), MovementMode::Walk);
+        run(&mut body, MovementInput::default(), 1, &world);
+        assert!(body.on_ground);
+
+        // jump held the whole time, it only takes again once landed
+        let jump = MovementInput {
+            jump: true,
+            ..MovementInput::default()
+        };
+        let mut highest = 0.0f32;
+        let mut landings = 0;
+        for _ in 0..120 {
+            let was_on_ground = body.on_ground;
+            body.step(&jump, &config, DT, &world);
+            highest = highest.max(body.position.y);
+            if !was_on_ground && body.on_ground {
+                landings += 1;
+            }
+        }
+        let apex = config.jump_velocity.powi(2) / (2.0 * config.gravity);
+        assert!(
+            highest <= apex + 1e-3,
+            "jumped to {highest}, a single jump reaches {apex}"
+        );
+        assert!(highest > apex * 0.9);
+        assert!(landings > 0);
+    }
+
+    #[test]
+    fn fly_ignores_collision() {
+        let mut world = Voxels::floor();
+        world.fill(IVec3::new(2, -1, -8), IVec3::new(3, 3, 8));
+        let mut body = PlayerBody::new(Vec3::new(0.5, 0.5, 0.5), MovementMode::Fly);
+        run(&mut body, walk_east(), 60, &world);
+        let expected = 0.5 + PhysicsConfig::default().fly_speed;
+        assert!(
+            (body.position.x - expected).abs() < 1e-3,
+            "flew to {}",
+            body.position.x
+        );
+        assert_eq!(body.position.y, 0.5);
+        assert!(!body.on_ground);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::compute::throttler::TokenBucket;
+use crate::world::network::MsgChunkDataRequest;
+use crate::world::server::world::CHUNK_DIM;
+use glam::{IVec3, USizeVec3};
+use std::f64::consts::PI;
+use std::time::Instant;
+
+// the budget holds a player's whole interest sphere, so the initial load goes through at once,
+// and refills it this often, more than moving around or a teleport ever needs
+const INTEREST_REFILL_SECONDS: f64 = 4.0;
+const DISTANCE_SLACK: i32 = 2; // chunks, the client may be a bit ahead of its last position update
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChunkRequestRejection {
+    NotConnected,
+    BadCount,
+    OutOfBounds,
+    TooFar,
+    RateLimited,
+    __Count,
+}
+crate::impl_try_from_uint!(u8 => ChunkRequestRejection);
+
+/// what a player may ask for, a request breaking any limit is rejected whole
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChunkRequestLimits {
+    chunk_half_extents: IVec3,
+    max_distance_sq: i32,
+    interest_volume: f64, // chunks within a player's simulation distance
+}
+
+impl ChunkRequestLimits {
+    pub fn new(max_world_size: USizeVec3, simulation_distance: usize) -> Self {
+        let max_distance = simulation_distance as i32 + DISTANCE_SLACK;
+        Self {
+            chunk_half_extents: (max_world_size / (CHUNK_DIM * 2)).as_ivec3() + 1,
+            max_distance_sq: max_distance * max_distance,
+            interest_volume: 4.0 / 3.0 * PI * (max_distance as f64).powi(3),
+        }
+    }
+
+    pub fn validate<'a>(
+        &self,
+        request: &'a MsgChunkDataRequest,
+        player_chunk: IVec3,
+    ) -> Result<&'a [IVec3], ChunkRequestRejection> {
+        let positions = request
+            .positions()
+            .filter(|positions| !positions.is_empty())
+            .ok_or(ChunkRequestRejection::BadCount)?;
+        for position in positions {
+            if position.abs().cmpgt(self.chunk_half_extents).any() {
+                return Err(ChunkRequestRejection::OutOfBounds);
+            }
+            if player_chunk.distance_squared(*position) > self.max_distance_sq {
+                return Err(ChunkRequestRejection::TooFar);
+            }
+        }
+        Ok(positions)
+    }
+}
+
+/// per player budget of requested chunks
+#[derive(Debug)]
+pub(crate) struct ChunkRequestLimiter {
+    budget: TokenBucket,
+}
+
+impl ChunkRequestLimiter {
+    pub fn new(limits: &ChunkRequestLimits) -> Self {
+        let rate = limits.interest_volume / INTEREST_REFILL_SECONDS;
+        Self {
+            budget: TokenBucket::new(rate, limits.interest_volume),
+        }
+    }
+
+    pub fn try_request(&mut self, count: usize, now: Instant) -> bool {
+        self.budget.refill(now);
+        self.budget.try_take(count as f64)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ChunkRequestMetrics {
+    pub accepted: u64,
+    pub chunks_requested: u64,
+    rejected: [u64; ChunkRequestRejection::__Count as usize],
+    reported_rejections: u64,
+}
+
+impl ChunkRequestMetrics {
+    pub fn accept(&mut self, chunk_count: usize) {
+        self.accepted += 1;
+        self.chunks_requested += chunk_count as u64;
+    }
+
+    pub fn reject(&mut self, reason: ChunkRequestRejection) {
+        self.rejected[reason as usize] += 1;
+    }
+
+    pub fn rejected(&self, reason: ChunkRequestRejection) -> u64 {
+        self.rejected[reason as usize]
+    }
+
+    pub fn total_rejected(&self) -> u64 {
+        self.rejected.iter().sum()
+    }
+
+    /// a summary of rejections, only when there were new ones since the last report
+    pub fn report(&mut self) -> Option<String> {
+        let total = self.total_rejected();
+        if total == self.reported_rejections {
+            return None;
+        }
+        self.reported_rejections = total;
+        let by_reason: Vec<String> = (0..ChunkRequestRejection::__Count as u8)
+            .filter_map(|i| ChunkRequestRejection::try_from(i).ok())
+            .filter(|reason| self.rejected(*reason) > 0)
+            .map(|reason| format!("{:?}: {}", reason, self.rejected(reason)))
+            .collect();
+        Some(format!(
+            "chunk requests: {} accepted ({} chunks), {} rejected [{}]",
+            self.accepted,
+            self.chunks_requested,
+            total,
+            by_reason.join(", ")
+        ))
+    }
+}
@@ -0,0 +1,583 @@
+use crate::world::entity::EntityData;
+use crate::world::network::DisconnectReason;
+use crate::world::server::block::VoxelBlock;
+use crate::world::server::edit::{
+    self, BlockRegion, EditRecorder, EditReport, Mirror, PasteOptions, Rotation,
+};
+use crate::world::server::{ServerWorld, World};
+use crate::world::time::TICKS_PER_DAY;
+use glam::{I64Vec3, IVec3, Vec3};
+use rustc_hash::FxHashMap;
+use std::fmt;
+use std::str::{FromStr, SplitWhitespace};
+
+// regions wider than this are refused, editing them would stall the server
+const MAX_EDIT_SIZE: i64 = 256;
+
+/// who ran a command, the reply goes back to them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    Console,
+    Player(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    Player,
+    Operator, // the console, and players connecting from `ServerWorldConfig::operators`
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Empty,
+    UnknownCommand(String),
+    PermissionDenied,
+    MissingArgument(&'static str),
+    InvalidArgument(&'static str, String),
+    TooManyArguments,
+    PlayerNotFound(String),
+    Failed(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Empty => write!(f, "no command given"),
+            CommandError::UnknownCommand(name) => write!(f, "unknown command {name}, try help"),
+            CommandError::PermissionDenied => write!(f, "you may not run that"),
+            CommandError::MissingArgument(name) => write!(f, "missing <{name}>"),
+            CommandError::InvalidArgument(name, value) => write!(f, "invalid <{name}>: {value}"),
+            CommandError::TooManyArguments => write!(f, "too many arguments"),
+            CommandError::PlayerNotFound(name) => write!(f, "no player named {name}"),
+            CommandError::Failed(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// the words after a command's name, taken in order by the command
+pub struct CommandArgs<'a> {
+    words: SplitWhitespace<'a>,
+}
+
+impl<'a> CommandArgs<'a> {
+    pub fn next(&mut self, name: &'static str) -> Result<&'a str, CommandError> {
+        self.words.next().ok_or(CommandError::MissingArgument(name))
+    }
+
+    pub fn optional(&mut self) -> Option<&'a str> {
+        self.words.next()
+    }
+
+    pub fn parse<T: FromStr>(&mut self, name: &'static str) -> Result<T, CommandError> {
+        let word = self.next(name)?;
+        word.parse()
+            .map_err(|_| CommandError::InvalidArgument(name, word.to_string()))
+    }
+
+    /// call once everything was taken, so typos don't go unnoticed
+    pub fn finish(mut self) -> Result<(), CommandError> {
+        match self.words.next() {
+            Some(_) => Err(CommandError::TooManyArguments),
+            None => Ok(()),
+        }
+    }
+}
+
+/// runs the command, returning the reply
+pub type CommandFn =
+    fn(&mut ServerWorld, CommandSource, CommandArgs) -> Result<String, CommandError>;
+
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub permission: Permission,
+    pub run: CommandFn,
+}
+
+pub struct CommandRegistry {
+    commands: FxHashMap<&'static str, Command>,
+}
+
+impl CommandRegistry {
+    pub fn empty() -> Self {
+        Self {
+            commands: FxHashMap::default(),
+        }
+    }
+
+    /// replaces any command of the same name
+    pub fn register(&mut self, command: Command) {
+        self.commands.insert(command.name, command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Command> {
+        self.commands.get(name).copied()
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.commands.values()
+    }
+
+    /// splits a line into the command and its arguments
+    pub fn parse<'a>(&self, line: &'a str) -> Result<(Command, CommandArgs<'a>), CommandError> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or(CommandError::Empty)?;
+        let command = self
+            .get(name)
+            .ok_or_else(|| CommandError::UnknownCommand(name.to_string()))?;
+        Ok((command, CommandArgs { words }))
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        use Permission::*;
+        let mut registry = Self::empty();
+        for (name, usage, permission, run) in [
+            ("help", "help", Player, help as CommandFn),
+            ("stop", "stop", Operator, stop),
+            ("save", "save", Operator, save),
+            ("list", "list", Player, list),
+            ("kick", "kick <player>", Operator, kick),
+            ("tp", "tp <player> (<x> <y> <z> | <target>)", Operator, tp),
+            (
+                "time",
+                "time [set <tick> | freeze | unfreeze]",
+                Operator,
+                time,
+            ),
+            ("seed", "seed", Player, seed),
+            (
+                "fill",
+                "fill <x1> <y1> <z1> <x2> <y2> <z2> <block>",
+                Operator,
+                fill,
+            ),
+            (
+                "replace",
+                "replace <x1> <y1> <z1> <x2> <y2> <z2> <from> <to>",
+                Operator,
+                replace,
+            ),
+            (
+                "hollow",
+                "hollow <x1> <y1> <z1> <x2> <y2> <z2>",
+                Operator,
+                hollow,
+            ),
+            ("undo", "undo", Operator, undo),
+            ("redo", "redo", Operator, redo),
+            ("copy", "copy <x1> <y1> <z1> <x2> <y2> <z2>", Operator, copy),
+            (
+                "paste",
+                "paste <x> <y> <z> [cw90 | cw180 | cw270] [mirror-x | mirror-z] [skip-empty]",
+                Operator,
+                paste,
+            ),
+            (
+                "summon",
+                "summon (marker | item <block> <count> | falling-block <block>)",
+                Operator,
+                summon,
+            ),
+            ("gen-stats", "gen-stats", Operator, gen_stats),
+        ] {
+            registry.register(Command {
+                name,
+                usage,
+                permission,
+                run,
+            });
+        }
+        registry
+    }
+}
+
+fn help(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    args: CommandArgs,
+) -> Result<String, CommandError> {
+    args.finish()?;
+    let permission = server.permission(source);
+    let mut usages: Vec<_> = server
+        .commands
+        .commands()
+        .filter(|command| command.permission <= permission)
+        .map(|command| command.usage)
+        .collect();
+    usages.sort();
+    Ok(usages.join("\n"))
+}
+
+fn stop(
+    server: &mut ServerWorld,
+    _: CommandSource,
+    args: CommandArgs,
+) -> Result<String, CommandError> {
+    args.finish()?;
+    server.stopping = true;
+    Ok("stopping the server".to_string())
+}
+
+fn save(
+    server: &mut ServerWorld,
+    _: CommandSource,
+    args: CommandArgs,
+) -> Result<String, CommandError> {
+    args.finish()?;
+    server
+        .save()
+        .map_err(|e| CommandError::Failed(format!("failed to save: {e}")))?;
+    Ok("saved".to_string())
+}
+
+fn list(
+    server: &mut ServerWorld,
+    _: CommandSource,
+    args: CommandArgs,
+) -> Result<String, CommandError> {
+    args.finish()?;
+    let mut names: Vec<&str> = server
+        .session
+        .players
+        .values()
+        .map(|p| p.player.name.as_str())
+        .collect();
+    names.sort();
+    Ok(format!(
+        "{}/{} players: {}",
+        names.len(),
+        server.config.max_players,
+        names.join(", ")
+    ))
+}
+
+fn kick(
+    server: &mut ServerWorld,
+    _: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let name = args.next("player")?;
+    args.finish()?;
+    let player_id = find_player(server, name)?;
+    server.disconnect_player(player_id, DisconnectReason::Kicked);
+    Ok(format!("kicked {name}"))
+}
+
+fn tp(
+    server: &mut ServerWorld,
+    _: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let name = args.next("player")?;
+    let player_id = find_player(server, name)?;
+    let world = server.session.players[&player_id].player.location.world;
+    let first = args.next("x | target")?;
+    let position = match first.parse::<f32>() {
+        Ok(x) => Vec3::new(x, args.parse("y")?, args.parse("z")?),
+        Err(_) => {
+            let target = &server.session.players[&find_player(server, first)?].player;
+            if target.location.world != world {
+                return Err(CommandError::Failed(format!("{first} is in another world")));
+            }
+            target.location.position
+        }
+    };
+    args.finish()?;
+    if !position.is_finite() {
+        return Err(CommandError::InvalidArgument("x", first.to_string()));
+    }
+    server.teleport_player(player_id, position);
+    Ok(format!(
+        "teleported {name} to {:.1} {:.1} {:.1}",
+        position.x, position.y, position.z
+    ))
+}
+
+fn time(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let world_index = server.source_world(source);
+    enum TimeChange {
+        Set(u64),
+        Frozen(bool),
+    }
+    // parsed whole before anything changes, so a bad line changes nothing
+    let change = match args.optional() {
+        None => None,
+        Some("set") => Some(TimeChange::Set(args.parse("tick")?)),
+        Some("freeze") => Some(TimeChange::Frozen(true)),
+        Some("unfreeze") => Some(TimeChange::Frozen(false)),
+        Some(other) => {
+            return Err(CommandError::InvalidArgument("action", other.to_string()));
+        }
+    };
+    args.finish()?;
+    match change {
+        None => {}
+        Some(TimeChange::Set(tick_of_day)) => {
+            if tick_of_day >= TICKS_PER_DAY {
+                let value = tick_of_day.to_string();
+                return Err(CommandError::InvalidArgument("tick", value));
+            }
+            let day = server.session.world(world_index).clock().time.day();
+            server.set_world_time(world_index, day * TICKS_PER_DAY + tick_of_day);
+        }
+        Some(TimeChange::Frozen(frozen)) => server.set_world_time_frozen(world_index, frozen),
+    }
+    let time = server.session.world(world_index).clock().time;
+    Ok(format!(
+        "day {}, tick {} of {}{}",
+        time.day(),
+        time.time_of_day(),
+        TICKS_PER_DAY,
+        if time.frozen { ", frozen" } else { "" }
+    ))
+}
+
+fn seed(
+    server: &mut ServerWorld,
+    _: CommandSource,
+    args: CommandArgs,
+) -> Result<String, CommandError> {
+    args.finish()?;
+    Ok(format!("seed: {}", server.config.world_config.seed))
+}
+
+fn gen_stats(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    args: CommandArgs,
+) -> Result<String, CommandError> {
+    args.finish()?;
+    let world_index = server.source_world(source);
+    Ok(server
+        .session
+        .world(world_index)
+        .generation_stats()
+        .to_string())
+}
+
+/// spawns an entity where the player stands, or at spawn from the console
+fn summon(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let data = match args.next("kind")? {
+        "marker" => EntityData::Marker,
+        "item" => EntityData::Item {
+            block: parse_block(&mut args, "block")?,
+            count: args.parse("count")?,
+        },
+        "falling-block" => EntityData::FallingBlock {
+            block: parse_block(&mut args, "block")?,
+        },
+        other => return Err(CommandError::InvalidArgument("kind", other.to_string())),
+    };
+    args.finish()?;
+    let world_index = server.source_world(source);
+    let position = match server.source_player(source) {
+        Some(server_player) => server_player.player.location.position,
+        None => server
+            .session
+            .world(world_index)
+            .meta()
+            .spawn
+            .unwrap_or_default(),
+    };
+    let id = server
+        .session
+        .world_mut(world_index)
+        .entities_mut()
+        .spawn(data, position);
+    Ok(format!("summoned {:?} {}", data.kind(), id.0))
+}
+
+fn fill(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let region = parse_region(&mut args)?;
+    let block = parse_block(&mut args, "block")?;
+    args.finish()?;
+    edit_as_player(server, source, |world, recorder| {
+        edit::fill(world, region, block, recorder)
+    })
+}
+
+fn replace(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let region = parse_region(&mut args)?;
+    let from = parse_block(&mut args, "from")?;
+    let to = parse_block(&mut args, "to")?;
+    args.finish()?;
+    edit_as_player(server, source, |world, recorder| {
+        edit::replace(world, region, from, to, recorder)
+    })
+}
+
+fn hollow(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let region = parse_region(&mut args)?;
+    args.finish()?;
+    edit_as_player(server, source, |world, recorder| {
+        edit::hollow(world, region, recorder)
+    })
+}
+
+fn undo(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    args: CommandArgs,
+) -> Result<String, CommandError> {
+    args.finish()?;
+    let player_id = editing_player(source)?;
+    match server.session.undo(player_id) {
+        Some(report) => Ok(edit_reply(&report)),
+        None => Ok("nothing to undo".to_string()),
+    }
+}
+
+fn redo(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    args: CommandArgs,
+) -> Result<String, CommandError> {
+    args.finish()?;
+    let player_id = editing_player(source)?;
+    match server.session.redo(player_id) {
+        Some(report) => Ok(edit_reply(&report)),
+        None => Ok("nothing to redo".to_string()),
+    }
+}
+
+fn copy(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let region = parse_region(&mut args)?;
+    args.finish()?;
+    let player_id = editing_player(source)?;
+    let world_index = server.source_world(source);
+    let clipboard = edit::copy(server.session.world(world_index), region);
+    let size = clipboard.size();
+    if let Some(server_player) = server.session.players.get_mut(&player_id) {
+        server_player.clipboard = Some(clipboard);
+    }
+    Ok(format!("copied {}x{}x{} blocks", size.x, size.y, size.z))
+}
+
+fn paste(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    mut args: CommandArgs,
+) -> Result<String, CommandError> {
+    let origin = parse_position(&mut args)?;
+    let mut options = PasteOptions::default();
+    while let Some(word) = args.optional() {
+        match word {
+            "cw90" => options.rotation = Rotation::Cw90,
+            "cw180" => options.rotation = Rotation::Cw180,
+            "cw270" => options.rotation = Rotation::Cw270,
+            "mirror-x" => options.mirror = Mirror::X,
+            "mirror-z" => options.mirror = Mirror::Z,
+            "skip-empty" => options.skip_empty = true,
+            other => return Err(CommandError::InvalidArgument("option", other.to_string())),
+        }
+    }
+    let player_id = editing_player(source)?;
+    // taken for the edit, which needs the session, and put back after
+    let clipboard = server
+        .session
+        .players
+        .get_mut(&player_id)
+        .and_then(|server_player| server_player.clipboard.take())
+        .ok_or_else(|| CommandError::Failed("nothing copied yet".to_string()))?;
+    let result = edit_as_player(server, source, |world, recorder| {
+        edit::paste(world, &clipboard, origin, options, recorder)
+    });
+    if let Some(server_player) = server.session.players.get_mut(&player_id) {
+        server_player.clipboard = Some(clipboard);
+    }
+    result
+}
+
+/// edits are journaled per player for undo, so only players can make them
+fn editing_player(source: CommandSource) -> Result<usize, CommandError> {
+    match source {
+        CommandSource::Player(player_id) => Ok(player_id),
+        CommandSource::Console => Err(CommandError::Failed("only players can edit".to_string())),
+    }
+}
+
+fn edit_as_player<F>(
+    server: &mut ServerWorld,
+    source: CommandSource,
+    f: F,
+) -> Result<String, CommandError>
+where
+    F: FnOnce(&mut dyn World, &mut EditRecorder) -> EditReport,
+{
+    let player_id = editing_player(source)?;
+    let world_index = server.source_world(source);
+    let report = server
+        .session
+        .edit(player_id, world_index, f)
+        .ok_or_else(|| CommandError::Failed("not connected".to_string()))?;
+    Ok(edit_reply(&report))
+}
+
+fn edit_reply(report: &EditReport) -> String {
+    match report.unloaded_chunks {
+        0 => format!("changed {} blocks", report.changed_blocks),
+        unloaded => format!(
+            "changed {} blocks, {unloaded} chunks in the way weren't loaded",
+            report.changed_blocks
+        ),
+    }
+}
+
+fn parse_position(args: &mut CommandArgs) -> Result<IVec3, CommandError> {
+    Ok(IVec3::new(
+        args.parse("x")?,
+        args.parse("y")?,
+        args.parse("z")?,
+    ))
+}
+
+fn parse_region(args: &mut CommandArgs) -> Result<BlockRegion, CommandError> {
+    let region = BlockRegion::new(parse_position(args)?, parse_position(args)?);
+    let size = region.max.as_i64vec3() - region.min.as_i64vec3() + I64Vec3::ONE;
+    if size.max_element() > MAX_EDIT_SIZE {
+        let reason = format!("regions are at most {MAX_EDIT_SIZE} blocks across");
+        return Err(CommandError::Failed(reason));
+    }
+    Ok(region)
+}
+
+/// blocks are given by their raw value
+fn parse_block(args: &mut CommandArgs, name: &'static str) -> Result<VoxelBlock, CommandError> {
+    Ok(VoxelBlock {
+        value: args.parse(name)?,
+    })
+}
+
+fn find_player(server: &ServerWorld, name: &str) -> Result<usize, CommandError> {
+    server
+        .session
+        .player_by_name(name)
+        .ok_or_else(|| CommandError::PlayerNotFound(name.to_string()))
+}
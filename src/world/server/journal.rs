@@ -0,0 +1,94 @@
+use crate::world::server::world::World;
+use crate::world::server::world::edit::{EditBatch, EditRecorder, EditReport};
+use std::collections::VecDeque;
+
+/// per-player undo/redo history, bounded by the memory its batches hold
+pub(crate) struct EditJournal {
+    undo: VecDeque<EditBatch>,
+    redo: Vec<EditBatch>,
+    size_bytes: usize,
+    max_size_bytes: usize,
+}
+
+impl EditJournal {
+    pub(crate) fn new(max_size_bytes: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            size_bytes: 0,
+            max_size_bytes,
+        }
+    }
+
+    /// records a new edit, which invalidates anything that could be redone
+    pub(crate) fn push(&mut self, batch: EditBatch) {
+        for batch in self.redo.drain(..) {
+            self.size_bytes -= batch.size_bytes();
+        }
+        self.push_undo(batch);
+    }
+
+    pub(crate) fn undo(&mut self, world: &mut dyn World) -> Option<EditReport> {
+        let batch = self.undo.pop_back()?;
+        self.size_bytes -= batch.size_bytes();
+        let mut recorder = EditRecorder::new(batch.world);
+        let report = batch.apply(world, &mut recorder);
+        if let Some(redo_batch) = recorder.finish() {
+            self.size_bytes += redo_batch.size_bytes();
+            self.redo.push(redo_batch);
+            self.evict();
+        }
+        Some(report)
+    }
+
+    pub(crate) fn redo(&mut self, world: &mut dyn World) -> Option<EditReport> {
+        let batch = self.redo.pop()?;
+        self.size_bytes -= batch.size_bytes();
+        let mut recorder = EditRecorder::new(batch.world);
+        let report = batch.apply(world, &mut recorder);
+        if let Some(undo_batch) = recorder.finish() {
+            self.push_undo(undo_batch);
+        }
+        Some(report)
+    }
+
+    pub(crate) fn next_undo_world(&self) -> Option<usize> {
+        self.undo.back().map(|batch| batch.world)
+    }
+
+    pub(crate) fn next_redo_world(&self) -> Option<usize> {
+        self.redo.last().map(|batch| batch.world)
+    }
+
+    fn push_undo(&mut self, batch: EditBatch) {
+        let batch_size = batch.size_bytes();
+        if batch_size > self.max_size_bytes {
+            // too large to ever fit, keeping older history would make undo skip this edit
+            self.clear();
+            return;
+        }
+        self.size_bytes += batch_size;
+        self.undo.push_back(batch);
+        self.evict();
+    }
+
+    /// drops the oldest history until the journal fits its budget again
+    fn evict(&mut self) {
+        while self.size_bytes > self.max_size_bytes {
+            if let Some(batch) = self.undo.pop_front() {
+                self.size_bytes -= batch.size_bytes();
+            } else if !self.redo.is_empty() {
+                let batch = self.redo.remove(0);
+                self.size_bytes -= batch.size_bytes();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.size_bytes = 0;
+    }
+}
@@ -1,46 +1,139 @@
+mod chunk_requests;
+mod commands;
+mod journal;
+mod movement;
+mod replication;
+mod runner;
 mod session;
+mod streaming;
 mod world;
 
-use crate::compute::MIB;
+use crate::compute::geo::world_to_chunk_pos;
 use crate::voxer_network;
 use crate::world::network::{
-    MsgChunkData, MsgChunkDataEmpty, MsgChunkDataRequest, MsgSetPositionRequest, NetworkHandle,
-    ServerMessage, ServerMessageTag,
+    BlockDelta, ChunkStreamingMode, ConnectDenyReason, DisconnectReason, Endpoint, Keepalive,
+    MAX_BLOCK_DELTAS_PER_BATCH, MsgChat, MsgChatRequest, MsgChunkData, MsgChunkDataAck,
+    MsgChunkDataEmpty, MsgChunkDataRequest, MsgConnect, MsgConnectDeny, MsgConnectRequest,
+    MsgDisconnect, MsgEntityDespawn, MsgEntitySpawn, MsgEntityUpdate, MsgPing, MsgPlayerJoin,
+    MsgPlayerLeave, MsgPong, MsgSetPosition, MsgSetPositionRequest, MsgWorldTime, NetworkHandle,
+    PROTOCOL_VERSION, ServerMessage, ServerMessageTag, SetPositionKind, WireMessage, chunk_codec,
+    encoded_chunk_message, handshake_protocol_version, send_block_deltas,
 };
-use crate::world::server::session::{ServerPlayerSession, ServerWorldSession};
+use crate::world::server::chunk_requests::{
+    ChunkRequestLimiter, ChunkRequestLimits, ChunkRequestMetrics, ChunkRequestRejection,
+};
+pub use crate::world::server::commands::{
+    Command, CommandArgs, CommandError, CommandFn, CommandRegistry, CommandSource, Permission,
+};
+use crate::world::server::journal::EditJournal;
+use crate::world::server::movement::{MovementLimits, MovementValidator};
+use crate::world::server::replication::{EntityEvent, EntityInterest};
+pub use crate::world::server::runner::{ServerThread, TickLoopConfig, TickMetrics};
+use crate::world::server::world::chunk::VoxelChunk;
+use crate::world::server::world::spawn::VOID_LEVEL;
+use crate::world::server::world::storage::WorldStorage;
+use crate::world::server::session::{ChunkUpdate, ServerPlayerSession, ServerWorldSession};
+use crate::world::server::streaming::{ChunkStreamer, stream_offsets};
 pub use crate::world::server::world::*;
 use crate::world::server::world::{Earth, World};
+use crate::world::physics::PhysicsConfig;
 use crate::world::session::{PlayerLocation, PlayerSession};
-use glam::Vec3;
-use std::net::SocketAddr;
+use glam::{IVec3, Quat, Vec3};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use voxer_network::NetworkDeserializable;
 
+const TIME_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+const FORGET_CHUNKS_INTERVAL: Duration = Duration::from_secs(2);
+const PLAYER_SNAPSHOT_INTERVAL: Duration = Duration::from_millis(50);
+
 #[derive(Debug)]
 pub struct ServerWorldConfig {
     pub world_config: WorldConfig,
     pub simulation_distance: usize,
+    pub max_players: usize,
+    pub allow_noclip: bool, // trust clients that say they are flying through blocks
+    pub chunk_streaming: ChunkStreamingMode,
+    pub chunk_bandwidth: usize, // bytes per second pushed to each player in push mode
+    pub edit_history_size: usize, // bytes of undo history kept per player
+    pub world_directory: Option<PathBuf>, // worlds are not persisted without one
+    // addresses whose players may run any command. names are whatever clients say they are,
+    // so until players are authenticated operators can only be told apart by where they connect from
+    pub operators: Vec<IpAddr>,
 }
 
 pub struct ServerWorld {
     config: ServerWorldConfig,
     network: NetworkHandle,
     session: ServerWorldSession,
+    movement_limits: MovementLimits,
+    chunk_request_limits: ChunkRequestLimits,
+    chunk_request_metrics: ChunkRequestMetrics,
+    commands: CommandRegistry,
+    stopping: bool, // a stop command ran, whoever drives the server should shut it down
+    stream_offsets: Vec<IVec3>, // nearest-first chunk offsets pushed around players
+    last_time_sync: Instant,
+    last_chunk_forget: Instant,
+    last_player_snapshot: Instant,
+    started: Instant,                      // snapshot times count from here
+    pending_connects: Vec<PendingConnect>, // accepted, waiting for the spawn area to generate
+}
+
+struct PendingConnect {
+    addr: SocketAddr,
+    name: String,
+    keepalive: Keepalive, // the client pings while it waits, gone quiet it is dropped
 }
 
 impl ServerWorld {
-    pub fn new(config: ServerWorldConfig) -> Self {
+    pub fn new(config: ServerWorldConfig, mut network: NetworkHandle) -> Self {
         let chunks_size_hint = config.simulation_distance.pow(4); // fixme arbitrary number
-        let earth = Box::new(Earth::new(config.world_config.clone(), chunks_size_hint));
+        let earth_storage = config.world_directory.as_ref().map(|dir| {
+            WorldStorage::open(dir.join("earth")).expect("Failed to open world storage")
+        });
+        let earth = Box::new(Earth::new(
+            config.world_config.clone(),
+            chunks_size_hint,
+            earth_storage,
+        ));
         let worlds: Vec<Box<dyn World>> = vec![earth];
         let session = ServerWorldSession::new(worlds);
 
-        let socket_addr = SocketAddr::from(([0, 0, 0, 0], 3100));
-        let mut network = NetworkHandle::bind(socket_addr, MIB * 4);
-        network.listen();
+        let world_half_extents = config.world_config.max_world_size.as_vec3() / 2.0;
+        let movement_limits = MovementLimits::new(
+            &PhysicsConfig::default(),
+            world_half_extents,
+            config.allow_noclip,
+        );
+        let chunk_request_limits = ChunkRequestLimits::new(
+            config.world_config.max_world_size,
+            config.simulation_distance,
+        );
+
+        let stream_offsets = match config.chunk_streaming {
+            ChunkStreamingMode::Push => stream_offsets(config.simulation_distance as u32 - 1),
+            _ => Vec::new(),
+        };
+
+        network.listen(Endpoint::Server);
         Self {
             config,
             network,
             session,
+            movement_limits,
+            chunk_request_limits,
+            chunk_request_metrics: ChunkRequestMetrics::default(),
+            commands: CommandRegistry::default(),
+            stopping: false,
+            stream_offsets,
+            last_time_sync: Instant::now(),
+            last_chunk_forget: Instant::now(),
+            last_player_snapshot: Instant::now(),
+            started: Instant::now(),
+            pending_connects: Vec::new(),
         }
     }
 
@@ -48,72 +141,749 @@ impl ServerWorld {
         self.session.start();
     }
 
-    pub fn tick(&mut self) {
+    /// advances the server by `dt`, meant to be called at a fixed rate, see `ServerThread`
+    pub fn tick(&mut self, dt: Duration) {
         for message in self.network.take_messages(64) {
+            let addr = message.message.src;
             self.handle_network_message(message);
+            self.forget_unless_connected(addr);
+        }
+        self.session.tick(dt);
+        for addr in self.network.resend_unacked() {
+            if let Some(player_id) = self.session.player_by_addr(addr) {
+                self.disconnect_player(player_id, DisconnectReason::Timeout);
+            }
+        }
+        self.keep_players_alive();
+        self.connect_pending_players();
+        self.respawn_fallen_players();
+        self.send_chunk_updates();
+        self.stream_chunks();
+        self.replicate_entities();
+        if self.last_player_snapshot.elapsed() >= PLAYER_SNAPSHOT_INTERVAL {
+            self.last_player_snapshot = Instant::now();
+            self.snapshot_players();
+        }
+        if self.last_time_sync.elapsed() >= TIME_SYNC_INTERVAL {
+            self.last_time_sync = Instant::now();
+            for world_index in 0..self.session.world_count() {
+                self.sync_world_time(world_index);
+            }
+        }
+        if self.last_chunk_forget.elapsed() >= FORGET_CHUNKS_INTERVAL {
+            self.last_chunk_forget = Instant::now();
+            self.forget_distant_chunks();
+            if let Some(report) = self.chunk_request_metrics.report() {
+                println!("{report}");
+            }
+        }
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        self.session.save()
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping
+    }
+
+    pub fn commands_mut(&mut self) -> &mut CommandRegistry {
+        &mut self.commands
+    }
+
+    /// runs a command line, without its leading `/`, and sends the reply to whoever ran it
+    pub fn run_command(&mut self, source: CommandSource, line: &str) {
+        let reply = match self.execute_command(source, line) {
+            Ok(reply) => reply,
+            Err(e) => e.to_string(),
+        };
+        match source {
+            CommandSource::Console => println!("{reply}"),
+            CommandSource::Player(player_id) => {
+                for reply_line in reply.lines() {
+                    self.send_system_message(player_id, reply_line);
+                }
+            }
+        }
+    }
+
+    fn execute_command(
+        &mut self,
+        source: CommandSource,
+        line: &str,
+    ) -> Result<String, CommandError> {
+        let (command, args) = self.commands.parse(line)?;
+        if command.permission > self.permission(source) {
+            return Err(CommandError::PermissionDenied);
+        }
+        if let Some(server_player) = self.source_player(source) {
+            println!("{} ran /{}", server_player.player.name, line);
+        }
+        (command.run)(self, source, args)
+    }
+
+    fn source_player(&self, source: CommandSource) -> Option<&ServerPlayerSession> {
+        match source {
+            CommandSource::Console => None,
+            CommandSource::Player(player_id) => self.session.players.get(&player_id),
+        }
+    }
+
+    fn permission(&self, source: CommandSource) -> Permission {
+        if source == CommandSource::Console {
+            return Permission::Operator;
+        }
+        match self.source_player(source) {
+            Some(p) if self.config.operators.contains(&p.addr.ip()) => Permission::Operator,
+            _ => Permission::Player,
+        }
+    }
+
+    /// the world a command applies to, the console's is the first one
+    fn source_world(&self, source: CommandSource) -> usize {
+        self.source_player(source)
+            .map_or(0, |p| p.player.location.world)
+    }
+
+    pub fn set_world_time(&mut self, world_index: usize, ticks: u64) {
+        self.session.world_mut(world_index).clock_mut().time.ticks = ticks;
+        self.sync_world_time(world_index);
+    }
+
+    pub fn set_world_time_frozen(&mut self, world_index: usize, frozen: bool) {
+        self.session.world_mut(world_index).clock_mut().time.frozen = frozen;
+        self.sync_world_time(world_index);
+    }
+
+    /// disconnects every player, telling them the server is going away
+    pub fn shutdown(&mut self) {
+        let player_ids: Vec<usize> = self.session.players.keys().copied().collect();
+        for player_id in player_ids {
+            self.disconnect_player(player_id, DisconnectReason::ServerShutdown);
         }
-        self.session.tick();
+        self.pending_connects.clear();
+        self.network.flush_capture();
+    }
+
+    fn disconnect_player(&mut self, player_id: usize, reason: DisconnectReason) {
+        let Some(server_player) = self.session.remove_player(player_id) else {
+            return;
+        };
+        self.send_player_leave(&server_player.player);
+        if reason != DisconnectReason::Quit {
+            let msg = MsgDisconnect::new(reason);
+            self.network.send(msg, &server_player.addr).unwrap();
+        }
+        self.network.forget_peer(server_player.addr);
+    }
+
+    /// anyone can send reliable messages, state is only kept for connected players
+    fn forget_unless_connected(&self, addr: SocketAddr) {
+        let connected = self.session.player_by_addr(addr).is_some()
+            || self
+                .pending_connects
+                .iter()
+                .any(|pending| pending.addr == addr);
+        if !connected {
+            self.network.forget_peer(addr);
+        }
+    }
+
+    fn keep_players_alive(&mut self) {
+        let now = Instant::now();
+        let mut timed_out = Vec::new();
+        for server_player in self.session.players.values_mut() {
+            if server_player.keepalive.is_timed_out(now) {
+                timed_out.push(server_player.player.id);
+            } else if let Some(ping) = server_player.keepalive.poll_ping(now) {
+                self.network.send(ping, &server_player.addr).unwrap();
+            }
+        }
+        for player_id in timed_out {
+            self.disconnect_player(player_id, DisconnectReason::Timeout);
+        }
+        self.pending_connects.retain(|pending| {
+            let timed_out = pending.keepalive.is_timed_out(now);
+            if timed_out {
+                let msg = MsgDisconnect::new(DisconnectReason::Timeout);
+                self.network.send(msg, &pending.addr).unwrap();
+                self.network.forget_peer(pending.addr);
+            }
+            !timed_out
+        });
+    }
+
+    fn connect_pending_players(&mut self) {
+        let Some(spawn) = self.session.world(0).meta().spawn else {
+            return;
+        };
+        for pending in std::mem::take(&mut self.pending_connects) {
+            self.connect_player(pending.addr, pending.name, spawn);
+        }
+    }
+
+    /// checks a connect request, returns the name the player will join with
+    fn validate_connect(
+        &mut self,
+        addr: SocketAddr,
+        request: &MsgConnectRequest,
+    ) -> Result<String, ConnectDenyReason> {
+        let name = request
+            .name()
+            .filter(|name| !name.is_empty() && name.trim() == *name)
+            .filter(|name| !name.chars().any(char::is_control))
+            .ok_or(ConnectDenyReason::InvalidName)?;
+
+        // a client connecting again from the same address replaces its previous session
+        if let Some(player_id) = self.session.player_by_addr(addr) {
+            let server_player = self.session.remove_player(player_id).unwrap();
+            self.send_player_leave(&server_player.player);
+        }
+        self.pending_connects.retain(|pending| pending.addr != addr);
+
+        let name_taken = self.session.player_by_name(name).is_some()
+            || self
+                .pending_connects
+                .iter()
+                .any(|pending| pending.name == name);
+        if name_taken {
+            return Err(ConnectDenyReason::DuplicateName);
+        }
+        if self.session.players.len() + self.pending_connects.len() >= self.config.max_players {
+            return Err(ConnectDenyReason::ServerFull);
+        }
+        Ok(name.to_string())
+    }
+
+    fn connect_player(&mut self, addr: SocketAddr, name: String, spawn: Vec3) {
+        let player = PlayerSession {
+            id: self.session.allocate_player_id(),
+            name,
+            location: PlayerLocation {
+                world: 0,
+                position: spawn,
+                rotation: Quat::IDENTITY,
+            },
+        };
+        let msg = MsgConnect::new(
+            player.id as u32,
+            player.location.world as u32,
+            self.config.world_config.seed,
+            spawn,
+            self.config.chunk_streaming,
+        );
+        let server_player = ServerPlayerSession {
+            player,
+            addr,
+            entity_interest: EntityInterest::default(),
+            journal: EditJournal::new(self.config.edit_history_size),
+            keepalive: Keepalive::new(Instant::now()),
+            known_chunks: FxHashSet::default(),
+            movement: MovementValidator::new(Instant::now()),
+            streamer: ChunkStreamer::new(self.config.chunk_bandwidth),
+            chunk_requests: ChunkRequestLimiter::new(&self.chunk_request_limits),
+            clipboard: None,
+        };
+        let player_id = server_player.player.id;
+        self.session.add_player(server_player);
+        self.network.send(msg, &addr).unwrap();
+        self.send_player_join(player_id);
+    }
+
+    /// tells everyone else about a player that just connected, and them about everyone else
+    fn send_player_join(&self, player_id: usize) {
+        let joined = &self.session.players[&player_id];
+        let joined_msg = MsgPlayerJoin::new(player_id as u32, &joined.player.name);
+        for other in self.session.players.values() {
+            if other.player.id == player_id {
+                continue;
+            }
+            self.network.send(joined_msg, &other.addr).unwrap();
+            let other_msg = MsgPlayerJoin::new(other.player.id as u32, &other.player.name);
+            self.network.send(other_msg, &joined.addr).unwrap();
+        }
+        self.broadcast_system_message(&format!("{} joined the game", joined.player.name));
+    }
+
+    fn send_player_leave(&self, player: &PlayerSession) {
+        let msg = MsgPlayerLeave {
+            player_id: player.id as u32,
+        };
+        for other in self.session.players.values() {
+            self.network.send(msg, &other.addr).unwrap();
+        }
+        self.broadcast_system_message(&format!("{} left the game", player.name));
+    }
+
+    /// sends `text` from the server to every player, truncated to `MAX_CHAT_LEN` bytes
+    pub fn broadcast_system_message(&self, text: &str) {
+        println!("{text}");
+        let msg = MsgChat::system(text);
+        for server_player in self.session.players.values() {
+            self.network.send(msg, &server_player.addr).unwrap();
+        }
+    }
+
+    /// sends `text` from the server to one player, like the output of a command they ran
+    pub fn send_system_message(&self, player_id: usize, text: &str) {
+        let Some(server_player) = self.session.players.get(&player_id) else {
+            return;
+        };
+        let msg = MsgChat::system(text);
+        self.network.send(msg, &server_player.addr).unwrap();
+    }
+
+    /// relays what a player said to everyone, them included
+    fn relay_chat(&self, player_id: usize, text: &str) {
+        let sender = &self.session.players[&player_id].player;
+        println!("<{}> {}", sender.name, text);
+        let msg = MsgChat::player(player_id as u32, &sender.name, text);
+        for server_player in self.session.players.values() {
+            self.network.send(msg, &server_player.addr).unwrap();
+        }
+    }
+
+    fn respawn_fallen_players(&mut self) {
+        for (player_id, spawn) in self.session.fallen_players(VOID_LEVEL) {
+            self.teleport_player(player_id, spawn);
+        }
+    }
+
+    pub fn teleport_player(&mut self, player_id: usize, position: Vec3) {
+        self.set_player_position(player_id, position, SetPositionKind::Teleport);
+    }
+
+    fn set_player_position(&mut self, player_id: usize, position: Vec3, kind: SetPositionKind) {
+        let Some((addr, teleport_id)) = self.session.teleport_player(player_id, position) else {
+            return;
+        };
+        let msg = MsgSetPosition::new(position, teleport_id, kind);
+        self.network.send(msg, &addr).unwrap();
+    }
+
+    fn sync_world_time(&self, world_index: usize) {
+        let time = self.session.world(world_index).clock().time;
+        for server_player in self.session.players.values() {
+            if server_player.player.location.world != world_index {
+                continue;
+            }
+            let msg = MsgWorldTime::new(time);
+            self.network.send(msg, &server_player.addr).unwrap();
+        }
+    }
+
+    /// sends edited chunks to every player that has them, as block deltas when only a few changed
+    fn send_chunk_updates(&mut self) {
+        let updates = self.session.take_chunk_updates();
+        if updates.is_empty() {
+            return;
+        }
+        // fully resent chunks are encoded for the first player that has them, reused for the rest
+        let mut encoded = FxHashMap::default();
+        for server_player in self.session.players.values() {
+            let world_index = server_player.player.location.world;
+            let world = self.session.world(world_index);
+            let mut deltas = Vec::new();
+            for (key @ (update_world, position), update) in updates.iter() {
+                if *update_world != world_index || !server_player.known_chunks.contains(position) {
+                    continue;
+                }
+                let Some(chunk) = world.chunk(*position) else {
+                    continue;
+                };
+                let indices = match update {
+                    ChunkUpdate::Blocks(indices) => indices,
+                    ChunkUpdate::Full => {
+                        let encoded = encoded.entry(*key).or_insert_with(|| encode_chunk(chunk));
+                        let (msg, _) = chunk_message(chunk, encoded.as_deref());
+                        self.network.send_to(msg, &server_player.addr).unwrap();
+                        continue;
+                    }
+                };
+                deltas.extend(indices.iter().map(|&index| BlockDelta {
+                    chunk: *position,
+                    index,
+                    block: chunk.block(VoxelChunk::index_local(index)),
+                }));
+            }
+            for batch in deltas.chunks(MAX_BLOCK_DELTAS_PER_BATCH) {
+                send_block_deltas(&self.network, batch, &server_player.addr).unwrap();
+            }
+        }
+    }
+
+    fn stream_chunks(&mut self) {
+        if self.stream_offsets.is_empty() {
+            return;
+        }
+        let network = &self.network;
+        self.session
+            .stream_chunks(&self.stream_offsets, Instant::now(), |addr, chunk| {
+                let (msg, size) = chunk_message(chunk, encode_chunk(chunk).as_deref());
+                network.send_to(msg, &addr).unwrap();
+                size
+            });
+    }
+
+    /// clients drop chunks outside their range without telling us, mirror that here
+    fn forget_distant_chunks(&mut self) {
+        let drop_dist_sq = (self.config.simulation_distance as i32).pow(2) + 1;
+        for server_player in self.session.players.values_mut() {
+            let player_ch_pos = world_to_chunk_pos(server_player.player.location.position);
+            server_player
+                .known_chunks
+                .retain(|p| player_ch_pos.distance_squared(*p) <= drop_dist_sq);
+        }
+    }
+
+    fn replicate_entities(&mut self) {
+        let range = (self.config.simulation_distance * CHUNK_DIM) as f32;
+        let network = &self.network;
+        self.session.replicate_entities(range, |addr, event| {
+            let result = match event {
+                EntityEvent::Spawn(entity) => network.send(MsgEntitySpawn::new(entity), &addr),
+                EntityEvent::Update(entity) => network.send(MsgEntityUpdate::new(entity), &addr),
+                EntityEvent::Despawn(id) => network.send(MsgEntityDespawn { id }, &addr),
+            };
+            result.unwrap();
+        });
+    }
+
+    fn snapshot_players(&self) {
+        let range = (self.config.simulation_distance * CHUNK_DIM) as f32;
+        let time = self.started.elapsed().as_millis() as u32;
+        self.session
+            .snapshot_players(range, time, |addr, snapshot| {
+                self.network.send(*snapshot, &addr).unwrap();
+            });
     }
 
     fn handle_network_message(&mut self, message: ServerMessage) {
+        let player_id = self.session.player_by_addr(message.message.src);
+        if let Some(player_id) = player_id {
+            let server_player = self.session.players.get_mut(&player_id).unwrap();
+            server_player.keepalive.on_received(Instant::now());
+        } else if let Some(pending) = self
+            .pending_connects
+            .iter_mut()
+            .find(|pending| pending.addr == message.message.src)
+        {
+            pending.keepalive.on_received(Instant::now());
+        }
         match message.tag {
             ServerMessageTag::ChunkDataRequest => {
+                let Some(player_id) = player_id else {
+                    self.chunk_request_metrics
+                        .reject(ChunkRequestRejection::NotConnected);
+                    return;
+                };
                 let chunk_req_msg = MsgChunkDataRequest::deserialize(message.message.data);
-                let positions = &chunk_req_msg.positions[0..chunk_req_msg.count as usize];
-                let chunks = self.session.request_chunks_from_world(0, positions);
-                for chunk in chunks {
-                    match chunk.is_empty() {
-                        true => {
-                            let msg_data = MsgChunkDataEmpty {
-                                position: chunk.position,
-                            };
-                            let msg = Box::new(msg_data);
-                            self.network.send_to(msg, &message.message.src).unwrap();
-                        }
-                        false => {
-                            let msg_data = MsgChunkData {
-                                position: chunk.position,
-                                voxel_count: chunk.voxel_count,
-                                blocks: chunk.blocks,
-                            };
-                            let msg = Box::new(msg_data);
-                            self.network.send_to(msg, &message.message.src).unwrap();
-                        }
+                let server_player = self.session.players.get_mut(&player_id).unwrap();
+                let player_ch_pos = world_to_chunk_pos(server_player.player.location.position);
+                let positions = match self
+                    .chunk_request_limits
+                    .validate(&chunk_req_msg, player_ch_pos)
+                {
+                    Ok(positions) => positions,
+                    Err(reason) => {
+                        self.chunk_request_metrics.reject(reason);
+                        return;
                     }
+                };
+                if !server_player
+                    .chunk_requests
+                    .try_request(positions.len(), Instant::now())
+                {
+                    self.chunk_request_metrics
+                        .reject(ChunkRequestRejection::RateLimited);
+                    return;
+                }
+                self.chunk_request_metrics.accept(positions.len());
+                let world_index = server_player.player.location.world;
+                let chunks = self
+                    .session
+                    .request_chunks_from_world(world_index, positions);
+                let mut sent = Vec::with_capacity(chunks.len());
+                for chunk in chunks {
+                    let (msg, _) = chunk_message(chunk, encode_chunk(chunk).as_deref());
+                    self.network.send_to(msg, &message.message.src).unwrap();
+                    sent.push(chunk.position);
                 }
+                let server_player = self.session.players.get_mut(&player_id).unwrap();
+                server_player.known_chunks.extend(sent);
+            }
+            ServerMessageTag::ChunkDataAck => {
+                let Some(player_id) = player_id else {
+                    return;
+                };
+                let ack_msg = MsgChunkDataAck::deserialize(message.message.data);
+                self.session.ack_chunks(player_id, ack_msg.positions());
             }
             ServerMessageTag::SetPositionRequest => {
-                if self.session.player_by_addr(message.message.src).is_none() {
+                let Some(player_id) = player_id else {
                     return;
-                }
+                };
                 let position_req = MsgSetPositionRequest::deserialize(message.message.data);
-                let player_id = self.session.player_by_addr(message.message.src).unwrap();
-                self.session
-                    .players
-                    .get_mut(&player_id)
-                    .unwrap()
-                    .player
-                    .location
-                    .position = position_req.position;
+                let result = self.session.move_player(
+                    player_id,
+                    position_req.position,
+                    position_req.teleport_id,
+                    position_req.rotation(),
+                    position_req.noclip != 0,
+                    &self.movement_limits,
+                );
+                if let Some(Err(_)) = result {
+                    // put them back where we last accepted them
+                    let position = self.session.players[&player_id].player.location.position;
+                    self.set_player_position(player_id, position, SetPositionKind::Correction);
+                }
             }
             ServerMessageTag::ConnectRequest => {
-                let (pid, paddr) = (0, message.message.src);
-                let player = PlayerSession {
-                    id: pid,
-                    name: "bill".to_string(),
-                    location: PlayerLocation {
-                        world: 0,
-                        position: Vec3::ZERO,
-                    },
+                let addr = message.message.src;
+                let data = message.message.data;
+                let same_version = handshake_protocol_version(&data) == Some(PROTOCOL_VERSION);
+                if !same_version || data.len() != size_of::<MsgConnectRequest>() {
+                    let msg = MsgConnectDeny::new(ConnectDenyReason::BadVersion);
+                    self.network.send(msg, &addr).unwrap();
+                    return;
+                }
+                // a new connection, whatever is left of a previous one from this address is dropped
+                self.network.reset_peer(addr);
+                let request = MsgConnectRequest::deserialize(data);
+                let name = match self.validate_connect(addr, &request) {
+                    Ok(name) => name,
+                    Err(reason) => {
+                        let msg = MsgConnectDeny::new(reason);
+                        self.network.send(msg, &addr).unwrap();
+                        return;
+                    }
                 };
-                let server_player = ServerPlayerSession {
-                    player,
-                    addr: paddr,
+                match self.session.world(0).meta().spawn {
+                    Some(spawn) => self.connect_player(addr, name, spawn),
+                    None => self.pending_connects.push(PendingConnect {
+                        addr,
+                        name,
+                        keepalive: Keepalive::new(Instant::now()),
+                    }),
+                }
+            }
+            ServerMessageTag::ChatRequest => {
+                let Some(player_id) = player_id else {
+                    return;
                 };
-                self.session.add_player(server_player);
+                let chat_req = MsgChatRequest::deserialize(message.message.data);
+                match chat_req.text() {
+                    Ok(text) => match text.strip_prefix('/') {
+                        Some(line) => self.run_command(CommandSource::Player(player_id), line),
+                        None => self.relay_chat(player_id, text),
+                    },
+                    Err(e) => {
+                        let text = format!("message not sent: {:?}", e);
+                        self.send_system_message(player_id, &text);
+                    }
+                }
+            }
+            ServerMessageTag::Ping => {
+                // answered before connecting too, so clients waiting on spawn don't time out
+                let ping = MsgPing::deserialize(message.message.data);
+                let msg = MsgPong { id: ping.id };
+                self.network.send(msg, &message.message.src).unwrap();
+            }
+            ServerMessageTag::Pong => {
+                let Some(player_id) = player_id else {
+                    return;
+                };
+                let pong = MsgPong::deserialize(message.message.data);
+                let server_player = self.session.players.get_mut(&player_id).unwrap();
+                server_player.keepalive.on_pong(&pong, Instant::now());
+            }
+            ServerMessageTag::DisconnectRequest => {
+                let addr = message.message.src;
+                self.pending_connects.retain(|pending| pending.addr != addr);
+                if let Some(player_id) = player_id {
+                    self.disconnect_player(player_id, DisconnectReason::Quit);
+                }
+            }
+            // anything else is not sent to servers and was dropped on receiving
+            _ => {}
+        }
+    }
+}
+
+/// a chunk's codec bytes, None for empty chunks which are sent without any
+fn encode_chunk(chunk: &VoxelChunk) -> Option<Vec<u8>> {
+    (!chunk.is_empty()).then(|| chunk_codec::encode(&chunk.blocks))
+}
+
+/// empty chunks and chunks the codec can shrink are sent compact, anything else as raw blocks,
+/// `encoded` is what `encode_chunk` made of the chunk, returns the message and its size
+fn chunk_message(chunk: &VoxelChunk, encoded: Option<&[u8]>) -> (Box<dyn WireMessage>, usize) {
+    let Some(encoded) = encoded else {
+        let msg = MsgChunkDataEmpty {
+            position: chunk.position,
+        };
+        return (Box::new(msg), size_of::<MsgChunkDataEmpty>());
+    };
+    encoded_chunk_message(chunk.position, encoded).unwrap_or_else(|| {
+        let msg = MsgChunkData {
+            position: chunk.position,
+            voxel_count: chunk.voxel_count,
+            blocks: chunk.blocks,
+        };
+        (Box::new(msg), size_of::<MsgChunkData>())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute;
+    use crate::world::network::{ChatKind, LoopbackNetwork, NetworkConditions};
+    use glam::USizeVec3;
+
+    const TICK: Duration = Duration::from_millis(20);
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn server_addr() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 3100))
+    }
+
+    fn client_addr() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+
+    /// a small server whose spawn area is already generated, so players connect right away
+    fn started_server(network: NetworkHandle, chunk_streaming: ChunkStreamingMode) -> ServerWorld {
+        let config = ServerWorldConfig {
+            world_config: WorldConfig {
+                seed: 0,
+                noise_scale: 0.03,
+                max_world_size: USizeVec3::new(256, 256, 256),
+            },
+            simulation_distance: 4,
+            max_players: 4,
+            allow_noclip: true,
+            chunk_streaming,
+            chunk_bandwidth: compute::MIB,
+            edit_history_size: compute::MIB,
+            world_directory: None,
+            operators: Vec::new(),
+        };
+        let mut server = ServerWorld::new(config, network);
+        server.start_session();
+        let deadline = Instant::now() + TIMEOUT;
+        while server.session.world(0).meta().spawn.is_none() {
+            assert!(Instant::now() < deadline, "the spawn area never generated");
+            server.tick(TICK);
+            std::thread::sleep(TICK);
+        }
+        server
+    }
+
+    /// ticks the server until `accept` takes one of the messages the client received
+    fn wait_for<T>(
+        server: &mut ServerWorld,
+        client: &NetworkHandle,
+        mut accept: impl FnMut(ServerMessage) -> Option<T>,
+    ) -> T {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            assert!(Instant::now() < deadline, "timed out waiting on the server");
+            server.tick(TICK);
+            client.resend_unacked();
+            for message in client.try_iter_messages() {
+                if let Some(accepted) = accept(message) {
+                    return accepted;
+                }
             }
-            ServerMessageTag::Ping => unimplemented!(),
-            _ => unimplemented!(),
+            std::thread::sleep(TICK);
+        }
+    }
+
+    /// connects the client, which has to be told how the server streams chunks
+    fn connect(server: &mut ServerWorld, client: &NetworkHandle) {
+        client.reset_peer(server_addr());
+        let request = MsgConnectRequest::new("tester");
+        client.send(request, &server_addr()).unwrap();
+        let connect = wait_for(server, client, |message| {
+            let connect = message.tag == ServerMessageTag::Connect;
+            connect.then(|| MsgConnect::deserialize(message.message.data))
+        });
+        assert_eq!(
+            connect.chunk_streaming(),
+            Some(server.config.chunk_streaming)
+        );
+    }
+
+    /// every message has to be relayed back in order
+    fn chat(server: &mut ServerWorld, client: &NetworkHandle) {
+        let texts: Vec<String> = (0..8).map(|i| format!("message {i}")).collect();
+        for text in &texts {
+            let request = MsgChatRequest::new(text).unwrap();
+            client.send(request, &server_addr()).unwrap();
         }
+        let mut relayed = Vec::new();
+        wait_for(server, client, |message| {
+            if message.tag == ServerMessageTag::Chat {
+                let chat = MsgChat::deserialize(message.message.data);
+                if chat.kind() == Some(ChatKind::Player) {
+                    relayed.push(chat.text().unwrap().to_string());
+                }
+            }
+            (relayed.len() == texts.len()).then_some(())
+        });
+        assert_eq!(relayed, texts);
+    }
+
+    #[test]
+    fn connects_over_loopback() {
+        let loopback = LoopbackNetwork::default();
+        let mut client = NetworkHandle::loopback(&loopback, client_addr());
+        client.listen(Endpoint::Client);
+        let server_network = NetworkHandle::loopback(&loopback, server_addr());
+        let mut server = started_server(server_network, ChunkStreamingMode::Pull);
+        connect(&mut server, &client);
+        chat(&mut server, &client);
+    }
+
+    #[test]
+    fn streams_chunks_over_loopback_in_push_mode() {
+        let loopback = LoopbackNetwork::default();
+        let mut client = NetworkHandle::loopback(&loopback, client_addr());
+        client.listen(Endpoint::Client);
+        let server_network = NetworkHandle::loopback(&loopback, server_addr());
+        let mut server = started_server(server_network, ChunkStreamingMode::Push);
+        connect(&mut server, &client);
+        // pushed around the player without the client asking for any
+        wait_for(&mut server, &client, |message| {
+            let chunk = matches!(
+                message.tag,
+                ServerMessageTag::ChunkData
+                    | ServerMessageTag::ChunkDataEmpty
+                    | ServerMessageTag::ChunkDataEncoded
+            );
+            chunk.then_some(())
+        });
+        chat(&mut server, &client);
+    }
+
+    #[test]
+    fn connects_over_a_lossy_link() {
+        let conditions = NetworkConditions {
+            latency: Duration::from_millis(20),
+            jitter: Duration::from_millis(10),
+            loss: 0.2,
+            seed: 7,
+            ..NetworkConditions::default()
+        };
+        let loopback = LoopbackNetwork::default();
+        let mut client = NetworkHandle::loopback(&loopback, client_addr())
+            .with_conditions(conditions.reseeded(8));
+        client.listen(Endpoint::Client);
+        let server_network =
+            NetworkHandle::loopback(&loopback, server_addr()).with_conditions(conditions);
+        let mut server = started_server(server_network, ChunkStreamingMode::Pull);
+        connect(&mut server, &client);
+        chat(&mut server, &client);
     }
 }
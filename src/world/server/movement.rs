@@ -0,0 +1,122 @@
+use crate::world::physics::{MovementMode, PhysicsConfig, PlayerBody, VoxelCollider};
+use crate::world::server::block::VoxelBlock;
+use crate::world::server::world::World;
+use glam::{IVec3, Vec3};
+use std::time::{Duration, Instant};
+
+// absorbs jitter between client frames and the irregular rate position updates arrive at
+const SPEED_TOLERANCE: f32 = 1.5;
+const DISTANCE_SLACK: f32 = 2.0;
+// a player that went quiet for a while doesn't get to cover that whole time in one move
+const MAX_ELAPSED: Duration = Duration::from_secs(1);
+// how far short of the reported position a swept move may stop, for float error
+const SWEEP_EPSILON: f32 = 1e-3;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MovementLimits {
+    pub max_horizontal_speed: f32,
+    pub max_vertical_speed: f32,
+    pub world_half_extents: Vec3, // players must stay within +-this on x and z, and below it on y
+    pub allow_noclip: bool,
+}
+
+impl MovementLimits {
+    pub fn new(physics: &PhysicsConfig, world_half_extents: Vec3, allow_noclip: bool) -> Self {
+        Self {
+            max_horizontal_speed: physics.max_horizontal_speed() * SPEED_TOLERANCE,
+            max_vertical_speed: physics.max_vertical_speed() * SPEED_TOLERANCE,
+            world_half_extents,
+            allow_noclip,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MoveRejection {
+    TooFast,
+    OutOfBounds,
+    InsideBlock,
+}
+
+/// server side view of a player's movement, moves are checked against the last accepted position
+#[derive(Debug)]
+pub(crate) struct MovementValidator {
+    last_accepted: Instant,
+    teleport_id: u32,
+}
+
+impl MovementValidator {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            last_accepted: now,
+            teleport_id: 0,
+        }
+    }
+
+    /// the client echoes this, so moves sent before it saw our last correction can be told apart
+    pub fn teleport_id(&self) -> u32 {
+        self.teleport_id
+    }
+
+    pub fn check(
+        &mut self,
+        from: Vec3,
+        to: Vec3,
+        noclip: bool,
+        limits: &MovementLimits,
+        world: &dyn World,
+        now: Instant,
+    ) -> Result<(), MoveRejection> {
+        let bounds = limits.world_half_extents;
+        if to.x.abs() > bounds.x || to.z.abs() > bounds.z || to.y > bounds.y || !to.is_finite() {
+            return Err(MoveRejection::OutOfBounds);
+        }
+        let elapsed = now
+            .duration_since(self.last_accepted)
+            .min(MAX_ELAPSED)
+            .as_secs_f32();
+        let delta = to - from;
+        if delta.with_y(0.0).length() > limits.max_horizontal_speed * elapsed + DISTANCE_SLACK
+            || delta.y.abs() > limits.max_vertical_speed * elapsed + DISTANCE_SLACK
+        {
+            return Err(MoveRejection::TooFast);
+        }
+        if !(noclip && limits.allow_noclip) && !path_clear(from, to, world) {
+            return Err(MoveRejection::InsideBlock);
+        }
+        self.last_accepted = now;
+        Ok(())
+    }
+
+    /// the player is being moved by the server, returns the id the client has to echo from now on
+    pub fn teleport(&mut self, now: Instant) -> u32 {
+        self.last_accepted = now;
+        self.teleport_id = self.teleport_id.wrapping_add(1);
+        self.teleport_id
+    }
+}
+
+/// whether a player can get from `from` to `to` without passing through a known solid voxel,
+/// moving vertically first (stepping up) or last (walking off a ledge)
+fn path_clear(from: Vec3, to: Vec3, world: &dyn World) -> bool {
+    let world = KnownVoxels(world);
+    [[1, 0, 2], [0, 2, 1]].into_iter().any(|axes| {
+        let mut body = PlayerBody::new(from, MovementMode::Walk);
+        body.sweep(to - from, axes, &world);
+        body.position.distance(to) <= SWEEP_EPSILON
+    })
+}
+
+/// the server may not have every chunk a player moves through, only known blocks stop them
+struct KnownVoxels<'a>(&'a dyn World);
+
+impl VoxelCollider for KnownVoxels<'_> {
+    fn block_at(&self, voxel: IVec3) -> Option<VoxelBlock> {
+        self.0.block_at(voxel)
+    }
+
+    fn is_solid(&self, voxel: IVec3) -> bool {
+        self.block_at(voxel)
+            .is_some_and(|block| !block.is_transparent())
+    }
+}
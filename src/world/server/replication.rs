@@ -0,0 +1,130 @@
+use crate::world::entity::{Entity, EntityId, EntityStore};
+use glam::Vec3;
+use rustc_hash::FxHashSet;
+
+pub(crate) enum EntityEvent<'a> {
+    Spawn(&'a Entity),
+    Update(&'a Entity),
+    Despawn(EntityId),
+}
+
+/// tracks which entities a single client currently knows about
+#[derive(Default)]
+pub(crate) struct EntityInterest {
+    known: FxHashSet<EntityId>,
+}
+
+impl EntityInterest {
+    pub(crate) fn update<'a, F: FnMut(EntityEvent<'a>)>(
+        &mut self,
+        store: &'a EntityStore,
+        origin: Vec3,
+        range: f32,
+        mut f: F,
+    ) {
+        for id in store.despawned() {
+            if self.known.remove(id) {
+                f(EntityEvent::Despawn(*id));
+            }
+        }
+        let range_sq = range * range;
+        for entity in store.iter() {
+            let in_range = entity.position.distance_squared(origin) <= range_sq;
+            match (in_range, self.known.contains(&entity.id)) {
+                (true, false) => {
+                    self.known.insert(entity.id);
+                    f(EntityEvent::Spawn(entity));
+                }
+                (true, true) if store.is_dirty(entity.id) => f(EntityEvent::Update(entity)),
+                (false, true) => {
+                    self.known.remove(&entity.id);
+                    f(EntityEvent::Despawn(entity.id));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::entity::EntityData;
+
+    const RANGE: f32 = 10.0;
+
+    #[derive(Debug, PartialEq)]
+    enum Seen {
+        Spawn(EntityId),
+        Update(EntityId),
+        Despawn(EntityId),
+    }
+
+    fn replicate(
+        interest: &mut EntityInterest,
+        store: &mut EntityStore,
+        origin: Vec3,
+    ) -> Vec<Seen> {
+        let mut seen = Vec::new();
+        interest.update(store, origin, RANGE, |event| {
+            seen.push(match event {
+                EntityEvent::Spawn(entity) => Seen::Spawn(entity.id),
+                EntityEvent::Update(entity) => Seen::Update(entity.id),
+                EntityEvent::Despawn(id) => Seen::Despawn(id),
+            })
+        });
+        store.clear_changes();
+        seen
+    }
+
+    #[test]
+    fn spawn_update_despawn() {
+        let mut store = EntityStore::default();
+        let mut interest = EntityInterest::default();
+        let id = store.spawn(EntityData::Marker, Vec3::ZERO);
+        assert_eq!(
+            replicate(&mut interest, &mut store, Vec3::ZERO),
+            [Seen::Spawn(id)]
+        );
+        assert!(replicate(&mut interest, &mut store, Vec3::ZERO).is_empty());
+
+        store.get_mut(id).unwrap().position = Vec3::X;
+        assert_eq!(
+            replicate(&mut interest, &mut store, Vec3::ZERO),
+            [Seen::Update(id)]
+        );
+
+        store.despawn(id);
+        assert_eq!(
+            replicate(&mut interest, &mut store, Vec3::ZERO),
+            [Seen::Despawn(id)]
+        );
+        assert!(replicate(&mut interest, &mut store, Vec3::ZERO).is_empty());
+    }
+
+    #[test]
+    fn follows_range() {
+        let mut store = EntityStore::default();
+        let mut interest = EntityInterest::default();
+        let far = store.spawn(EntityData::Marker, Vec3::splat(RANGE * 2.0));
+        assert!(replicate(&mut interest, &mut store, Vec3::ZERO).is_empty());
+        // never sent, so never despawned either
+        store.despawn(far);
+        assert!(replicate(&mut interest, &mut store, Vec3::ZERO).is_empty());
+
+        let id = store.spawn(EntityData::Marker, Vec3::ZERO);
+        assert_eq!(
+            replicate(&mut interest, &mut store, Vec3::ZERO),
+            [Seen::Spawn(id)]
+        );
+        let far_away = Vec3::X * RANGE * 2.0;
+        assert_eq!(
+            replicate(&mut interest, &mut store, far_away),
+            [Seen::Despawn(id)]
+        );
+        assert_eq!(
+            replicate(&mut interest, &mut store, Vec3::ZERO),
+            [Seen::Spawn(id)]
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use crate::compute::timestep::FixedTimestep;
+use crate::world::server::{CommandSource, ServerWorld};
+use crossbeam::channel;
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const METRICS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy)]
+pub struct TickLoopConfig {
+    pub tick_rate: u32,
+    pub max_catch_up_ticks: u32, // run back to back after a stall, time beyond them is dropped
+}
+
+impl Default for TickLoopConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60,
+            max_catch_up_ticks: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TickMetrics {
+    pub ticks: u64,
+    pub overruns: u64, // ticks that took longer than a tick interval
+    pub stalls: u64,   // times the loop fell further behind than it may catch up
+    pub busy: Duration,
+    pub longest: Duration,
+}
+
+impl TickMetrics {
+    fn record(&mut self, duration: Duration, interval: Duration) {
+        self.ticks += 1;
+        self.busy += duration;
+        self.longest = self.longest.max(duration);
+        if duration > interval {
+            self.overruns += 1;
+        }
+    }
+
+    pub fn average(&self) -> Duration {
+        self.busy.div_f64(self.ticks.max(1) as f64)
+    }
+}
+
+impl fmt::Display for TickMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "server ticks: {}, {:.2}ms avg, {:.2}ms longest, {} overran, {} stalls",
+            self.ticks,
+            self.average().as_secs_f64() * 1000.0,
+            self.longest.as_secs_f64() * 1000.0,
+            self.overruns,
+            self.stalls
+        )
+    }
+}
+
+/// runs a server on its own thread at a fixed tick rate, whatever the caller is doing.
+/// players reach it through its network handle, the owner only starts and stops it
+pub struct ServerThread {
+    thread: Option<JoinHandle<()>>,
+    running: Arc<AtomicBool>,
+    console: channel::Sender<String>,
+    metrics: Arc<Mutex<TickMetrics>>,
+}
+
+impl ServerThread {
+    /// starts the server's session and ticks it until stopped, it is shut down and saved after
+    pub fn spawn(server: ServerWorld, config: TickLoopConfig) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let metrics = Arc::new(Mutex::new(TickMetrics::default()));
+        let (console, console_input) = channel::unbounded();
+        let thread = {
+            let running = running.clone();
+            let metrics = metrics.clone();
+            std::thread::Builder::new()
+                .name("server".to_string())
+                .spawn(move || run(server, config, &running, &console_input, &metrics))
+                .expect("failed to spawn the server thread")
+        };
+        Self {
+            thread: Some(thread),
+            running,
+            console,
+            metrics,
+        }
+    }
+
+    /// runs a command as the console would on the next tick, the reply is printed
+    pub fn run_command(&self, line: String) {
+        let _ = self.console.send(line);
+    }
+
+    pub fn metrics(&self) -> TickMetrics {
+        *self.metrics.lock()
+    }
+
+    /// true once the server stopped, on its own after a stop command or otherwise
+    pub fn is_finished(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_none_or(|thread| thread.is_finished())
+    }
+
+    /// stops the server and waits for it to shut down
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("server thread panicked");
+        }
+    }
+}
+
+impl Drop for ServerThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run(
+    mut server: ServerWorld,
+    config: TickLoopConfig,
+    running: &AtomicBool,
+    console_input: &channel::Receiver<String>,
+    metrics: &Mutex<TickMetrics>,
+) {
+    server.start_session();
+    let mut step = FixedTimestep::new(config.tick_rate, config.max_catch_up_ticks);
+    let interval = step.step();
+    let mut last_advance = Instant::now();
+    let mut last_report = Instant::now();
+    let mut reported = TickMetrics::default();
+    while running.load(Ordering::Relaxed) && !server.is_stopping() {
+        for line in console_input.try_iter() {
+            server.run_command(CommandSource::Console, &line);
+        }
+
+        let now = Instant::now();
+        let (ticks, discarded) = step.advance(now.duration_since(last_advance));
+        last_advance = now;
+        if discarded {
+            metrics.lock().stalls += 1;
+        }
+        for _ in 0..ticks {
+            let started = Instant::now();
+            server.tick(interval);
+            metrics.lock().record(started.elapsed(), interval);
+        }
+
+        // quiet unless the server is struggling
+        if last_report.elapsed() >= METRICS_REPORT_INTERVAL {
+            last_report = Instant::now();
+            let current = *metrics.lock();
+            if current.overruns > reported.overruns || current.stalls > reported.stalls {
+                println!("{current}");
+            }
+            reported = current;
+        }
+        std::thread::sleep(interval.mul_f32(1.0 - step.alpha()));
+    }
+
+    server.shutdown();
+    server
+        .save()
+        .unwrap_or_else(|e| println!("failed to save world: {:?}", e));
+}
@@ -1,34 +1,73 @@
 use crate::compute;
+use crate::compute::geo::world_to_chunk_pos;
 use crate::world::server::world::World;
 use crate::world::session::PlayerSession;
-use glam::IVec3;
-use rustc_hash::FxHashMap;
+use glam::{IVec3, Quat, Vec3};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::io;
 use std::net::SocketAddr;
 use crate::world::server::world::chunk::VoxelChunk;
+use crate::world::network::{Keepalive, MsgPlayerSnapshot};
+use crate::world::server::chunk_requests::ChunkRequestLimiter;
+use crate::world::server::journal::EditJournal;
+use crate::world::server::streaming::ChunkStreamer;
+use crate::world::server::movement::{MoveRejection, MovementLimits, MovementValidator};
+use crate::world::server::replication::{EntityEvent, EntityInterest};
+use crate::world::server::world::edit::{
+    Clipboard, EditRecorder, EditReport, MAX_BLOCK_DELTAS_PER_CHUNK,
+};
+use crate::world::server::world::spawn;
+use std::time::{Duration, Instant};
 
 pub(crate) struct ServerPlayerSession {
     pub player: PlayerSession,
     pub addr: SocketAddr,
+    pub entity_interest: EntityInterest,
+    pub journal: EditJournal,
+    pub keepalive: Keepalive,
+    pub known_chunks: FxHashSet<IVec3>, // sent to the client and not yet out of its range
+    pub movement: MovementValidator,
+    pub streamer: ChunkStreamer,
+    pub chunk_requests: ChunkRequestLimiter,
+    pub clipboard: Option<Clipboard>, // what the player copied last
+}
+
+/// what changed in an edited chunk since it was last sent to players
+pub(crate) enum ChunkUpdate {
+    Blocks(FxHashSet<u16>),
+    Full,
 }
 
 pub(crate) struct ServerWorldSession {
     worlds: Vec<Box<dyn World>>,
     pub(crate) players: FxHashMap<usize, ServerPlayerSession>,
     addr_to_player: FxHashMap<SocketAddr, usize>,
+    chunk_updates: FxHashMap<(usize, IVec3), ChunkUpdate>,
+    next_player_id: usize,
+    spawn_search_loaded: Vec<Option<usize>>, // loaded chunks per world when spawn was last searched
 }
 
 impl ServerWorldSession {
     pub(crate) fn new(worlds: Vec<Box<dyn World>>) -> Self {
         Self {
+            spawn_search_loaded: vec![None; worlds.len()],
             worlds,
             players: FxHashMap::default(),
             addr_to_player: FxHashMap::default(),
+            chunk_updates: FxHashMap::default(),
+            next_player_id: 0,
         }
     }
 
-    pub(crate) fn tick(&mut self) {
-        for world in self.worlds.iter_mut() {
-            world.tick();
+    pub(crate) fn tick(&mut self, dt: Duration) {
+        for (world, searched_at) in self.worlds.iter_mut().zip(&mut self.spawn_search_loaded) {
+            world.tick(dt.as_secs_f32());
+            // searched again only once generation delivered more chunks
+            let loaded = world.generation_stats().loaded;
+            if world.meta().spawn.is_none() && *searched_at != Some(loaded) {
+                *searched_at = Some(loaded);
+                world.meta_mut().spawn = spawn::find_spawn(world.as_mut());
+            }
             world.request_chunk_generation();
         }
     }
@@ -40,9 +79,24 @@ impl ServerWorldSession {
             .insert(player_session.player.id, player_session);
     }
 
-    pub(crate) fn remove_player(&mut self, player_id: usize) {
-        // todo remove from addr_to_player here?
-        self.players.remove(&player_id);
+    pub(crate) fn remove_player(&mut self, player_id: usize) -> Option<ServerPlayerSession> {
+        let server_player = self.players.remove(&player_id)?;
+        self.addr_to_player.remove(&server_player.addr);
+        Some(server_player)
+    }
+
+    /// ids are never reused within a session, so stale messages can't reach a newer player
+    pub(crate) fn allocate_player_id(&mut self) -> usize {
+        let id = self.next_player_id;
+        self.next_player_id += 1;
+        id
+    }
+
+    pub(crate) fn player_by_name(&self, name: &str) -> Option<usize> {
+        self.players
+            .values()
+            .find(|p| p.player.name == name)
+            .map(|p| p.player.id)
     }
 
     pub(crate) fn player_by_addr(&self, addr: SocketAddr) -> Option<usize> {
@@ -57,6 +111,237 @@ impl ServerWorldSession {
         self.worlds[world_index].request_chunks(chunk_positions)
     }
 
+    pub(crate) fn world(&self, world_index: usize) -> &dyn World {
+        self.worlds[world_index].as_ref()
+    }
+
+    pub(crate) fn world_mut(&mut self, world_index: usize) -> &mut dyn World {
+        self.worlds[world_index].as_mut()
+    }
+
+    pub(crate) fn world_count(&self) -> usize {
+        self.worlds.len()
+    }
+
+    /// runs an edit on behalf of a player, journaling it so it can be undone
+    pub(crate) fn edit<F>(&mut self, player_id: usize, world_index: usize, f: F) -> Option<EditReport>
+    where
+        F: FnOnce(&mut dyn World, &mut EditRecorder) -> EditReport,
+    {
+        let server_player = self.players.get_mut(&player_id)?;
+        let mut recorder = EditRecorder::new(world_index);
+        let report = f(self.worlds[world_index].as_mut(), &mut recorder);
+        if let Some(batch) = recorder.finish() {
+            server_player.journal.push(batch);
+        }
+        self.queue_chunk_updates(world_index, &report);
+        Some(report)
+    }
+
+    pub(crate) fn undo(&mut self, player_id: usize) -> Option<EditReport> {
+        let journal = &mut self.players.get_mut(&player_id)?.journal;
+        let world_index = journal.next_undo_world()?;
+        let report = journal.undo(self.worlds[world_index].as_mut())?;
+        self.queue_chunk_updates(world_index, &report);
+        Some(report)
+    }
+
+    pub(crate) fn redo(&mut self, player_id: usize) -> Option<EditReport> {
+        let journal = &mut self.players.get_mut(&player_id)?.journal;
+        let world_index = journal.next_redo_world()?;
+        let report = journal.redo(self.worlds[world_index].as_mut())?;
+        self.queue_chunk_updates(world_index, &report);
+        Some(report)
+    }
+
+    fn queue_chunk_updates(&mut self, world_index: usize, report: &EditReport) {
+        for position in report.affected_chunks.iter() {
+            let update = self
+                .chunk_updates
+                .entry((world_index, *position))
+                .or_insert_with(|| ChunkUpdate::Blocks(FxHashSet::default()));
+            let ChunkUpdate::Blocks(indices) = update else {
+                continue;
+            };
+            match report.block_deltas.get(position) {
+                Some(deltas) if indices.len() + deltas.len() <= MAX_BLOCK_DELTAS_PER_CHUNK => {
+                    indices.extend(deltas.iter().copied());
+                }
+                _ => *update = ChunkUpdate::Full,
+            }
+        }
+    }
+
+    pub(crate) fn take_chunk_updates(&mut self) -> Vec<((usize, IVec3), ChunkUpdate)> {
+        let updates: Vec<_> = self.chunk_updates.drain().collect();
+        // copies still in flight are outdated, let them be pushed again
+        for server_player in self.players.values_mut() {
+            let player_world = server_player.player.location.world;
+            for ((world_index, position), _) in updates.iter() {
+                if *world_index == player_world {
+                    server_player.streamer.invalidate(*position);
+                }
+            }
+        }
+        updates
+    }
+
+    /// pushes chunks around every player, `f` sends one and returns its size in bytes,
+    /// chunks that aren't loaded yet are requested from their world
+    pub(crate) fn stream_chunks<F>(&mut self, offsets: &[IVec3], now: Instant, mut f: F)
+    where
+        F: FnMut(SocketAddr, &VoxelChunk) -> usize,
+    {
+        let mut missing: FxHashMap<usize, Vec<IVec3>> = FxHashMap::default();
+        for server_player in self.players.values_mut() {
+            let addr = server_player.addr;
+            let world_index = server_player.player.location.world;
+            let world = self.worlds[world_index].as_ref();
+            let center = world_to_chunk_pos(server_player.player.location.position);
+            let known = &server_player.known_chunks;
+            server_player
+                .streamer
+                .stream(center, offsets, known, now, |position| match world.chunk(position) {
+                    Some(chunk) => Some(f(addr, chunk)),
+                    None => {
+                        missing.entry(world_index).or_default().push(position);
+                        None
+                    }
+                });
+        }
+        for (world_index, positions) in missing {
+            self.worlds[world_index].request_chunks(&positions);
+        }
+    }
+
+    /// marks chunks the player confirmed receiving as known
+    pub(crate) fn ack_chunks(&mut self, player_id: usize, positions: &[IVec3]) {
+        let Some(server_player) = self.players.get_mut(&player_id) else {
+            return;
+        };
+        for position in positions {
+            if server_player.streamer.on_ack(*position) {
+                server_player.known_chunks.insert(*position);
+            }
+        }
+    }
+
+    /// sends each player the entity changes within `range` of them, then clears the changes
+    pub(crate) fn replicate_entities<F: FnMut(SocketAddr, EntityEvent)>(
+        &mut self,
+        range: f32,
+        mut f: F,
+    ) {
+        for server_player in self.players.values_mut() {
+            let addr = server_player.addr;
+            let location = &server_player.player.location;
+            let store = self.worlds[location.world].entities();
+            server_player
+                .entity_interest
+                .update(store, location.position, range, |event| f(addr, event));
+        }
+        for world in self.worlds.iter_mut() {
+            world.entities_mut().clear_changes();
+        }
+    }
+
+    /// sends each player where the other players within `range` of them are
+    pub(crate) fn snapshot_players<F: FnMut(SocketAddr, &MsgPlayerSnapshot)>(
+        &self,
+        range: f32,
+        time: u32,
+        mut f: F,
+    ) {
+        let range_sq = range * range;
+        for receiver in self.players.values() {
+            let origin = &receiver.player.location;
+            let mut snapshot = MsgPlayerSnapshot::new(time);
+            for other in self.players.values() {
+                let location = &other.player.location;
+                if other.player.id == receiver.player.id
+                    || location.world != origin.world
+                    || location.position.distance_squared(origin.position) > range_sq
+                {
+                    continue;
+                }
+                if snapshot.is_full() {
+                    f(receiver.addr, &snapshot);
+                    snapshot = MsgPlayerSnapshot::new(time);
+                }
+                snapshot.push(other.player.id, location.position, location.rotation);
+            }
+            if !snapshot.is_empty() {
+                f(receiver.addr, &snapshot);
+            }
+        }
+    }
+
+    /// players below `void_level` paired with their world's spawn
+    pub(crate) fn fallen_players(&self, void_level: f32) -> Vec<(usize, Vec3)> {
+        self.players
+            .values()
+            .filter(|p| p.player.location.position.y < void_level)
+            .filter_map(|p| {
+                let spawn = self.worlds[p.player.location.world].meta().spawn?;
+                Some((p.player.id, spawn))
+            })
+            .collect()
+    }
+
+    /// checks a move reported by the client and applies it if valid,
+    /// None if the move predates the player's latest teleport and should be ignored
+    pub(crate) fn move_player(
+        &mut self,
+        player_id: usize,
+        position: Vec3,
+        teleport_id: u32,
+        rotation: Quat,
+        noclip: bool,
+        limits: &MovementLimits,
+    ) -> Option<Result<(), MoveRejection>> {
+        let server_player = self.players.get_mut(&player_id)?;
+        if teleport_id != server_player.movement.teleport_id() {
+            return None;
+        }
+        let location = &mut server_player.player.location;
+        // where they look isn't for us to check, as long as it is a rotation
+        if rotation.is_finite() && rotation.length_squared() > f32::EPSILON {
+            location.rotation = rotation.normalize();
+        }
+        let world = self.worlds[location.world].as_ref();
+        let result = server_player.movement.check(
+            location.position,
+            position,
+            noclip,
+            limits,
+            world,
+            Instant::now(),
+        );
+        if result.is_ok() {
+            location.position = position;
+        }
+        Some(result)
+    }
+
+    /// moves a player server side, returns where to tell them and the new teleport id
+    pub(crate) fn teleport_player(
+        &mut self,
+        player_id: usize,
+        position: Vec3,
+    ) -> Option<(SocketAddr, u32)> {
+        let server_player = self.players.get_mut(&player_id)?;
+        server_player.player.location.position = position;
+        let teleport_id = server_player.movement.teleport(Instant::now());
+        Some((server_player.addr, teleport_id))
+    }
+
+    pub(crate) fn save(&mut self) -> io::Result<()> {
+        for world in self.worlds.iter_mut() {
+            world.save()?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn start(&mut self) {
         self.worlds.first_mut().unwrap().start_simulation();
     }
@@ -0,0 +1,162 @@
+use crate::compute::geo::Sphere;
+use crate::compute::throttler::TokenBucket;
+use glam::IVec3;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::time::{Duration, Instant};
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(1); // unacknowledged chunks are pushed again
+const MAX_IN_FLIGHT_CHUNKS: usize = 256;
+const MAX_SCAN_PER_TICK: usize = 4096;
+const BURST_SECONDS: f64 = 0.25;
+
+/// chunk offsets within `radius`, nearest first
+pub(crate) fn stream_offsets(radius: u32) -> Vec<IVec3> {
+    let mut offsets: Vec<IVec3> = Sphere::discrete_points(IVec3::ZERO, radius).collect();
+    offsets.sort_by_key(|offset| offset.length_squared());
+    offsets
+}
+
+/// pushes chunks around a player nearest-first within a bandwidth budget, until they are acked
+pub(crate) struct ChunkStreamer {
+    center: Option<IVec3>,
+    cursor: usize, // every offset before this is known to the client
+    in_flight: FxHashMap<IVec3, Instant>,
+    budget: TokenBucket, // bytes
+}
+
+impl ChunkStreamer {
+    pub fn new(bytes_per_second: usize) -> Self {
+        let rate = bytes_per_second as f64;
+        Self {
+            center: None,
+            cursor: 0,
+            in_flight: FxHashMap::default(),
+            budget: TokenBucket::new(rate, rate * BURST_SECONDS),
+        }
+    }
+
+    /// `send` pushes one chunk and returns the bytes it sent, or None if it isn't available yet
+    pub fn stream<F>(
+        &mut self,
+        center: IVec3,
+        offsets: &[IVec3],
+        known: &FxHashSet<IVec3>,
+        now: Instant,
+        mut send: F,
+    ) where
+        F: FnMut(IVec3) -> Option<usize>,
+    {
+        if self.center != Some(center) {
+            self.center = Some(center);
+            self.cursor = 0;
+        }
+        self.in_flight
+            .retain(|_, sent_at| now.duration_since(*sent_at) < ACK_TIMEOUT);
+        self.budget.refill(now);
+
+        let mut all_known = true;
+        let end = offsets.len().min(self.cursor + MAX_SCAN_PER_TICK);
+        for i in self.cursor..end {
+            let position = center + offsets[i];
+            if known.contains(&position) {
+                if all_known {
+                    self.cursor = i + 1;
+                }
+                continue;
+            }
+            all_known = false;
+            if self.budget.available() <= 0.0 || self.in_flight.len() >= MAX_IN_FLIGHT_CHUNKS {
+                break;
+            }
+            if self.in_flight.contains_key(&position) {
+                continue;
+            }
+            if let Some(bytes) = send(position) {
+                self.budget.take(bytes as f64);
+                self.in_flight.insert(position, now);
+            }
+        }
+    }
+
+    /// whether the acked chunk was still awaited, stale acks are ignored
+    pub fn on_ack(&mut self, position: IVec3) -> bool {
+        self.in_flight.remove(&position).is_some()
+    }
+
+    /// the chunk changed while in flight, push it again
+    pub fn invalidate(&mut self, position: IVec3) {
+        self.in_flight.remove(&position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFSETS: [IVec3; 4] = [IVec3::ZERO, IVec3::X, IVec3::Y, IVec3::Z];
+    const CHUNK_BYTES: usize = 200;
+
+    /// streams around the origin, returns the positions pushed
+    fn stream(streamer: &mut ChunkStreamer, known: &FxHashSet<IVec3>, now: Instant) -> Vec<IVec3> {
+        let mut sent = Vec::new();
+        streamer.stream(IVec3::ZERO, &OFFSETS, known, now, |position| {
+            sent.push(position);
+            Some(CHUNK_BYTES)
+        });
+        sent
+    }
+
+    #[test]
+    fn cursor_skips_known_offsets() {
+        let mut streamer = ChunkStreamer::new(1_000_000);
+        let mut known = FxHashSet::from_iter([IVec3::ZERO, IVec3::X]);
+        let now = Instant::now();
+        assert_eq!(stream(&mut streamer, &known, now), [IVec3::Y, IVec3::Z]);
+        assert_eq!(streamer.cursor, 2);
+
+        known.extend([IVec3::Y, IVec3::Z]);
+        assert!(stream(&mut streamer, &known, now).is_empty());
+        assert_eq!(streamer.cursor, OFFSETS.len());
+    }
+
+    #[test]
+    fn stops_when_budget_is_spent() {
+        let mut streamer = ChunkStreamer::new(1000); // 250 bytes of burst
+        let known = FxHashSet::default();
+        let now = Instant::now();
+        // the second chunk overdraws the budget, nothing more goes out until it refills
+        assert_eq!(stream(&mut streamer, &known, now), [IVec3::ZERO, IVec3::X]);
+        assert!(stream(&mut streamer, &known, now).is_empty());
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(stream(&mut streamer, &known, later), [IVec3::Y, IVec3::Z]);
+    }
+
+    #[test]
+    fn resends_unacked_chunks_after_timeout() {
+        let mut streamer = ChunkStreamer::new(1_000_000);
+        let mut known = FxHashSet::default();
+        let now = Instant::now();
+        assert_eq!(stream(&mut streamer, &known, now), OFFSETS);
+        for position in [IVec3::ZERO, IVec3::X] {
+            assert!(streamer.on_ack(position));
+            known.insert(position);
+        }
+        assert!(!streamer.on_ack(IVec3::X), "acked twice");
+        assert!(stream(&mut streamer, &known, now).is_empty());
+
+        let later = now + ACK_TIMEOUT;
+        assert_eq!(stream(&mut streamer, &known, later), [IVec3::Y, IVec3::Z]);
+    }
+
+    #[test]
+    fn invalidated_chunks_are_pushed_again() {
+        let mut streamer = ChunkStreamer::new(1_000_000);
+        let known = FxHashSet::default();
+        let now = Instant::now();
+        assert_eq!(stream(&mut streamer, &known, now), OFFSETS);
+
+        streamer.invalidate(IVec3::Y);
+        assert_eq!(stream(&mut streamer, &known, now), [IVec3::Y]);
+    }
+}
@@ -24,6 +24,31 @@ impl VoxelChunk {
         self.voxel_count == 0
     }
 
+    /// flat index of a local position, in `blocks[x][y][z]` order
+    pub fn local_index(local: IVec3) -> u16 {
+        ((local.x as usize * CHUNK_DIM + local.y as usize) * CHUNK_DIM + local.z as usize) as u16
+    }
+
+    pub fn index_local(index: u16) -> IVec3 {
+        let index = index as i32;
+        let dim = CHUNK_DIM as i32;
+        IVec3::new(index / (dim * dim), (index / dim) % dim, index % dim)
+    }
+
+    #[inline]
+    pub fn block(&self, local: IVec3) -> VoxelBlock {
+        self.blocks[local.x as usize][local.y as usize][local.z as usize]
+    }
+
+    /// replaces a single block, keeping `voxel_count` in sync, returns the previous block
+    pub fn set_block(&mut self, local: IVec3, block: VoxelBlock) -> VoxelBlock {
+        let slot = &mut self.blocks[local.x as usize][local.y as usize][local.z as usize];
+        let previous = std::mem::replace(slot, block);
+        self.voxel_count += !block.is_transparent() as u32;
+        self.voxel_count -= !previous.is_transparent() as u32;
+        previous
+    }
+
     pub(crate) fn blocks_as_adj(&self) -> VoxelChunkAdjBlocks {
         let adj = [
             self.mx_layer_blocks(),
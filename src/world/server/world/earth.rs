@@ -1,40 +1,91 @@
 use crate::world::server::world::{World, WorldConfig};
 use crate::world::server::world::earth_gen::EarthGen;
 use crate::world::server::world::generation::{
-    WorldGenHandle, WorldGenRequest, WorldGenResponse,
+    GenerationStats, WorldGenHandle, WorldGenRequest, WorldGenResponse,
 };
-use glam::IVec3;
+use glam::{IVec2, IVec3};
 use rustc_hash::{FxHashMap, FxHashSet};
 use crate::world::server::world::chunk::VoxelChunk;
+use crate::world::server::world::heightmap::Heightmaps;
+use crate::world::server::world::spawn::WorldMeta;
+use crate::world::server::world::storage::WorldStorage;
+use crate::world::entity::EntityStore;
+use crate::world::time::WorldClock;
+use std::io;
+use std::time::Duration;
 
 pub struct Earth {
     config: WorldConfig,
     chunks: FxHashMap<IVec3, VoxelChunk>,
     generation_handle: WorldGenHandle<EarthGen>,
     generation_request_batch: FxHashSet<IVec3>,
+    entities: EntityStore,
+    clock: WorldClock,
+    heightmaps: Heightmaps,
+    meta: WorldMeta,
+    storage: Option<WorldStorage>,
+    stored_columns: FxHashSet<IVec2>, // columns already read back from storage, if they had a file
+    dirty_columns: FxHashSet<IVec2>,
 }
 
 impl Earth {
-    pub fn new(config: WorldConfig, chunks_size_hint: usize) -> Self {
+    pub fn new(config: WorldConfig, chunks_size_hint: usize, storage: Option<WorldStorage>) -> Self {
         let mut chunks = FxHashMap::default();
         chunks.reserve(chunks_size_hint);
         let earth_gen = EarthGen::new(config.clone());
+        let meta = storage
+            .as_ref()
+            .and_then(|storage| storage.load_meta().expect("Failed to load world meta"))
+            .unwrap_or_default();
         Self {
             config,
             chunks,
             generation_handle: WorldGenHandle::new(earth_gen),
             generation_request_batch: FxHashSet::default(),
+            entities: EntityStore::default(),
+            clock: WorldClock::default(),
+            heightmaps: Heightmaps::default(),
+            meta,
+            storage,
+            stored_columns: FxHashSet::default(),
+            dirty_columns: FxHashSet::default(),
         }
     }
+
+    /// reads a column back from storage the first time any of its chunks is requested
+    fn load_column(&mut self, column: IVec2) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        if !self.stored_columns.insert(column) {
+            return;
+        }
+        let stored = match storage.load_column(column) {
+            Ok(Some(stored)) => stored,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("failed to load column {column}: {e}");
+                return;
+            }
+        };
+        self.heightmaps.insert(column, stored.heightmap);
+        self.chunks
+            .extend(stored.chunks.into_iter().map(|chunk| (chunk.position, chunk)));
+    }
 }
 
 impl World for Earth {
-    fn tick(&mut self) {
+    fn tick(&mut self, dt: f32) {
+        self.entities.tick(dt);
+        self.clock.tick(Duration::from_secs_f32(dt));
         if let Ok(gen_response) = self.generation_handle.try_recv() {
             match gen_response {
-                WorldGenResponse::Chunks(chunks) => {
-                    self.chunks
-                        .extend(chunks.into_iter().map(|chunk| (chunk.position, chunk)));
+                WorldGenResponse::Chunks(chunks, _) => {
+                    for chunk in chunks {
+                        self.heightmaps.on_chunk_loaded(&chunk);
+                        self.dirty_columns.insert(Heightmaps::column_of(chunk.position));
+                        self.chunks.insert(chunk.position, chunk);
+                    }
                 }
                 _ => {}
             }
@@ -43,6 +94,11 @@ impl World for Earth {
 
     fn request_chunks(&mut self, positions: &[IVec3]) -> Vec<&VoxelChunk> {
         let mut chunks = Vec::with_capacity(positions.len());
+        for position in positions {
+            if !self.chunks.contains_key(position) {
+                self.load_column(Heightmaps::column_of(*position));
+            }
+        }
         for position in positions {
             match self.chunks.get(position) {
                 Some(chunk) => chunks.push(chunk),
@@ -54,6 +110,49 @@ impl World for Earth {
         chunks
     }
 
+    fn chunk(&self, position: IVec3) -> Option<&VoxelChunk> {
+        self.chunks.get(&position)
+    }
+
+    fn chunk_mut(&mut self, position: IVec3) -> Option<&mut VoxelChunk> {
+        self.chunks.get_mut(&position)
+    }
+
+    fn heightmaps(&self) -> &Heightmaps {
+        &self.heightmaps
+    }
+
+    fn on_chunks_edited(&mut self, positions: &[IVec3]) {
+        for position in positions {
+            self.heightmaps.on_chunk_edited(*position, &self.chunks);
+            self.dirty_columns.insert(Heightmaps::column_of(*position));
+        }
+    }
+
+    fn entities(&self) -> &EntityStore {
+        &self.entities
+    }
+
+    fn entities_mut(&mut self) -> &mut EntityStore {
+        &mut self.entities
+    }
+
+    fn clock(&self) -> &WorldClock {
+        &self.clock
+    }
+
+    fn clock_mut(&mut self) -> &mut WorldClock {
+        &mut self.clock
+    }
+
+    fn meta(&self) -> &WorldMeta {
+        &self.meta
+    }
+
+    fn meta_mut(&mut self) -> &mut WorldMeta {
+        &mut self.meta
+    }
+
     fn request_chunk_generation(&mut self) {
         let positions: Vec<IVec3> = self
             .generation_request_batch
@@ -69,6 +168,13 @@ impl World for Earth {
             .expect("Failed to send generation request");
     }
 
+    fn generation_stats(&self) -> GenerationStats {
+        GenerationStats {
+            loaded: self.chunks.len(),
+            ..self.generation_handle.stats()
+        }
+    }
+
     fn start_simulation(&mut self) {
         self.generation_handle.start_thread();
     }
@@ -76,4 +182,26 @@ impl World for Earth {
     fn stop_simulation(&mut self) {
         self.generation_handle.stop_thread()
     }
+
+    fn save(&mut self) -> io::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        storage.save_meta(&self.meta)?;
+        let mut column_chunks: FxHashMap<IVec2, Vec<&VoxelChunk>> = FxHashMap::default();
+        for chunk in self.chunks.values() {
+            let column = Heightmaps::column_of(chunk.position);
+            if self.dirty_columns.contains(&column) {
+                column_chunks.entry(column).or_default().push(chunk);
+            }
+        }
+        for (column, chunks) in column_chunks {
+            let Some(heightmap) = self.heightmaps.column(column) else {
+                continue;
+            };
+            storage.save_column(column, heightmap, chunks.into_iter())?;
+            self.dirty_columns.remove(&column);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,431 @@
+use crate::compute::geo::{AABB, IVec3Iter};
+use crate::world::server::world::block::VoxelBlock;
+use crate::world::server::world::chunk::VoxelChunk;
+use crate::world::server::world::{CHUNK_DIM, CHUNK_VOLUME, VoxelChunkBlocks, World};
+use glam::IVec3;
+use range3d::Range3D;
+use rustc_hash::FxHashMap;
+
+// chunks with more changed blocks than this are resent whole instead of as block deltas
+pub const MAX_BLOCK_DELTAS_PER_CHUNK: usize = 64;
+
+/// inclusive box of world voxel positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRegion {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl BlockRegion {
+    pub fn new(a: IVec3, b: IVec3) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    pub fn from_size(min: IVec3, size: IVec3) -> Self {
+        Self::new(min, min + size - IVec3::ONE)
+    }
+
+    pub fn size(&self) -> IVec3 {
+        self.max - self.min + IVec3::ONE
+    }
+
+    pub fn volume(&self) -> usize {
+        let size = self.size();
+        size.x as usize * size.y as usize * size.z as usize
+    }
+
+    pub fn contains(&self, position: IVec3) -> bool {
+        position.cmpge(self.min).all() && position.cmple(self.max).all()
+    }
+
+    pub fn is_shell(&self, position: IVec3) -> bool {
+        position.cmpeq(self.min).any() || position.cmpeq(self.max).any()
+    }
+
+    /// chunk positions touched by this region
+    pub fn chunks(&self) -> Range3D {
+        let dim = IVec3::splat(CHUNK_DIM as i32);
+        let min = self.min.div_euclid(dim);
+        let max = self.max.div_euclid(dim) + IVec3::ONE;
+        AABB::new(min.as_vec3(), max.as_vec3()).discrete_points()
+    }
+
+    /// the part of this region inside `chunk_position`, as exclusive chunk-local ranges
+    fn local_points(&self, chunk_position: IVec3) -> IVec3Iter {
+        let origin = chunk_position * CHUNK_DIM as i32;
+        let min = (self.min - origin).max(IVec3::ZERO);
+        let max = (self.max - origin + IVec3::ONE).min(IVec3::splat(CHUNK_DIM as i32));
+        IVec3Iter::new(min.x..max.x, min.y..max.y, min.z..max.z)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct EditReport {
+    pub affected_chunks: Vec<IVec3>,
+    // changed block indices of affected chunks with few enough changes to send as deltas
+    pub block_deltas: FxHashMap<IVec3, Vec<u16>>,
+    pub changed_blocks: usize,
+    pub unloaded_chunks: usize,
+}
+
+impl EditReport {
+    fn add_chunk(&mut self, position: IVec3, changed_indices: Vec<u16>) {
+        self.affected_chunks.push(position);
+        self.changed_blocks += changed_indices.len();
+        if changed_indices.len() <= MAX_BLOCK_DELTAS_PER_CHUNK {
+            self.block_deltas.insert(position, changed_indices);
+        }
+    }
+}
+
+/// visits every loaded block in `region` one chunk at a time, `f` returns the replacement if any
+pub fn edit_region<F>(
+    world: &mut dyn World,
+    region: BlockRegion,
+    recorder: &mut EditRecorder,
+    mut f: F,
+) -> EditReport
+where
+    F: FnMut(IVec3, VoxelBlock) -> Option<VoxelBlock>,
+{
+    let mut report = EditReport::default();
+    for chunk_position in region.chunks() {
+        let Some(chunk) = world.chunk_mut(chunk_position) else {
+            report.unloaded_chunks += 1;
+            continue;
+        };
+        let origin = chunk_position * CHUNK_DIM as i32;
+        let mut changed = Vec::new();
+        for local in region.local_points(chunk_position) {
+            let current = chunk.block(local);
+            let Some(block) = f(origin + local, current) else {
+                continue;
+            };
+            if block.value != current.value {
+                chunk.set_block(local, block);
+                recorder.record(chunk_position, local, current);
+                changed.push(VoxelChunk::local_index(local));
+            }
+        }
+        if !changed.is_empty() {
+            report.add_chunk(chunk_position, changed);
+        }
+    }
+    world.on_chunks_edited(&report.affected_chunks);
+    report
+}
+
+pub fn fill(
+    world: &mut dyn World,
+    region: BlockRegion,
+    block: VoxelBlock,
+    recorder: &mut EditRecorder,
+) -> EditReport {
+    edit_region(world, region, recorder, |_, _| Some(block))
+}
+
+pub fn replace(
+    world: &mut dyn World,
+    region: BlockRegion,
+    from: VoxelBlock,
+    to: VoxelBlock,
+    recorder: &mut EditRecorder,
+) -> EditReport {
+    edit_region(world, region, recorder, |_, current| {
+        (current.value == from.value).then_some(to)
+    })
+}
+
+/// clears everything inside the box, leaving its outer shell untouched
+pub fn hollow(
+    world: &mut dyn World,
+    region: BlockRegion,
+    recorder: &mut EditRecorder,
+) -> EditReport {
+    edit_region(world, region, recorder, |position, _| {
+        (!region.is_shell(position)).then_some(VoxelBlock::EMPTY)
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    size: IVec3,
+    blocks: Vec<VoxelBlock>,
+}
+
+impl Clipboard {
+    pub fn size(&self) -> IVec3 {
+        self.size
+    }
+
+    fn index(&self, local: IVec3) -> usize {
+        ((local.x * self.size.y + local.y) * self.size.z + local.z) as usize
+    }
+
+    pub fn block(&self, local: IVec3) -> VoxelBlock {
+        self.blocks[self.index(local)]
+    }
+}
+
+/// copies `region` into a clipboard, blocks in unloaded chunks are copied as empty
+pub fn copy(world: &dyn World, region: BlockRegion) -> Clipboard {
+    let mut clipboard = Clipboard {
+        size: region.size(),
+        blocks: vec![VoxelBlock::EMPTY; region.volume()],
+    };
+    for chunk_position in region.chunks() {
+        let Some(chunk) = world.chunk(chunk_position) else {
+            continue;
+        };
+        let origin = chunk_position * CHUNK_DIM as i32;
+        for local in region.local_points(chunk_position) {
+            let index = clipboard.index(origin + local - region.min);
+            clipboard.blocks[index] = chunk.block(local);
+        }
+    }
+    clipboard
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mirror {
+    #[default]
+    None,
+    X,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PasteOptions {
+    pub rotation: Rotation, // around the y axis, applied after mirroring
+    pub mirror: Mirror,
+    pub skip_empty: bool,
+}
+
+impl PasteOptions {
+    fn pasted_size(&self, size: IVec3) -> IVec3 {
+        match self.rotation {
+            Rotation::None | Rotation::Cw180 => size,
+            Rotation::Cw90 | Rotation::Cw270 => IVec3::new(size.z, size.y, size.x),
+        }
+    }
+
+    /// maps a position inside the pasted box back to the clipboard
+    fn source_position(&self, pasted: IVec3, size: IVec3) -> IVec3 {
+        let mut source = match self.rotation {
+            Rotation::None => pasted,
+            Rotation::Cw90 => IVec3::new(pasted.z, pasted.y, size.z - 1 - pasted.x),
+            Rotation::Cw180 => {
+                IVec3::new(size.x - 1 - pasted.x, pasted.y, size.z - 1 - pasted.z)
+            }
+            Rotation::Cw270 => IVec3::new(size.x - 1 - pasted.z, pasted.y, pasted.x),
+        };
+        match self.mirror {
+            Mirror::None => {}
+            Mirror::X => source.x = size.x - 1 - source.x,
+            Mirror::Z => source.z = size.z - 1 - source.z,
+        }
+        source
+    }
+}
+
+/// pastes the clipboard with its min corner at `origin`
+pub fn paste(
+    world: &mut dyn World,
+    clipboard: &Clipboard,
+    origin: IVec3,
+    options: PasteOptions,
+    recorder: &mut EditRecorder,
+) -> EditReport {
+    let region = BlockRegion::from_size(origin, options.pasted_size(clipboard.size));
+    edit_region(world, region, recorder, |position, _| {
+        let source = options.source_position(position - origin, clipboard.size);
+        let block = clipboard.block(source);
+        (!options.skip_empty || !block.is_transparent()).then_some(block)
+    })
+}
+
+
+// a dense chunk edit costs about as much as this many sparse changes
+const DENSE_CHUNK_EDIT_THRESHOLD: usize =
+    (size_of::<VoxelChunkBlocks>() + CHUNK_VOLUME / 8) / size_of::<SparseBlock>();
+
+#[derive(Debug, Clone, Copy)]
+struct SparseBlock {
+    index: u16,
+    block: VoxelBlock,
+}
+
+#[derive(Debug, Clone)]
+enum ChunkEditBlocks {
+    Sparse(Vec<SparseBlock>),
+    Dense {
+        mask: Box<[u64; CHUNK_VOLUME / 64]>,
+        blocks: Box<VoxelChunkBlocks>,
+    },
+}
+
+/// blocks of one chunk captured by an edit, usually the values they had before it
+#[derive(Debug, Clone)]
+struct ChunkEdit {
+    position: IVec3,
+    blocks: ChunkEditBlocks,
+}
+
+impl ChunkEdit {
+    fn new(position: IVec3) -> Self {
+        Self {
+            position,
+            blocks: ChunkEditBlocks::Sparse(Vec::new()),
+        }
+    }
+
+    fn push(&mut self, local: IVec3, block: VoxelBlock) {
+        let index = VoxelChunk::local_index(local);
+        match &mut self.blocks {
+            ChunkEditBlocks::Sparse(sparse) => {
+                sparse.push(SparseBlock { index, block });
+                if sparse.len() > DENSE_CHUNK_EDIT_THRESHOLD {
+                    self.densify();
+                }
+            }
+            ChunkEditBlocks::Dense { mask, blocks } => {
+                mask[index as usize / 64] |= 1u64 << (index % 64);
+                blocks[local.x as usize][local.y as usize][local.z as usize] = block;
+            }
+        }
+    }
+
+    fn densify(&mut self) {
+        let ChunkEditBlocks::Sparse(sparse) = &self.blocks else {
+            return;
+        };
+        let mut mask = Box::new([0u64; CHUNK_VOLUME / 64]);
+        let mut blocks = Box::new(VoxelChunkBlocks::default());
+        for entry in sparse {
+            let local = VoxelChunk::index_local(entry.index);
+            mask[entry.index as usize / 64] |= 1u64 << (entry.index % 64);
+            blocks[local.x as usize][local.y as usize][local.z as usize] = entry.block;
+        }
+        self.blocks = ChunkEditBlocks::Dense { mask, blocks };
+    }
+
+    fn for_each<F: FnMut(IVec3, VoxelBlock)>(&self, mut f: F) {
+        match &self.blocks {
+            ChunkEditBlocks::Sparse(sparse) => {
+                for entry in sparse {
+                    f(VoxelChunk::index_local(entry.index), entry.block);
+                }
+            }
+            ChunkEditBlocks::Dense { mask, blocks } => {
+                for (word_index, word) in mask.iter().enumerate() {
+                    let mut word = *word;
+                    while word != 0 {
+                        let index = (word_index * 64) as u16 + word.trailing_zeros() as u16;
+                        word &= word - 1;
+                        let local = VoxelChunk::index_local(index);
+                        f(local, blocks[local.x as usize][local.y as usize][local.z as usize]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn size_bytes(&self) -> usize {
+        size_of::<Self>()
+            + match &self.blocks {
+                ChunkEditBlocks::Sparse(sparse) => sparse.capacity() * size_of::<SparseBlock>(),
+                ChunkEditBlocks::Dense { .. } => {
+                    size_of::<VoxelChunkBlocks>() + CHUNK_VOLUME / 8
+                }
+            }
+    }
+}
+
+/// every block an edit replaced, grouped by chunk, applying it restores those blocks
+#[derive(Debug, Clone)]
+pub struct EditBatch {
+    pub world: usize,
+    chunks: Vec<ChunkEdit>,
+}
+
+impl EditBatch {
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        size_of::<Self>() + self.chunks.iter().map(ChunkEdit::size_bytes).sum::<usize>()
+    }
+
+    /// writes the batch back into `world` like any other edit, recording what it overwrote
+    pub fn apply(&self, world: &mut dyn World, recorder: &mut EditRecorder) -> EditReport {
+        let mut report = EditReport::default();
+        for chunk_edit in self.chunks.iter() {
+            let Some(chunk) = world.chunk_mut(chunk_edit.position) else {
+                report.unloaded_chunks += 1;
+                continue;
+            };
+            let mut changed = Vec::new();
+            chunk_edit.for_each(|local, block| {
+                let current = chunk.set_block(local, block);
+                if current.value != block.value {
+                    recorder.record(chunk_edit.position, local, current);
+                    changed.push(VoxelChunk::local_index(local));
+                }
+            });
+            if !changed.is_empty() {
+                report.add_chunk(chunk_edit.position, changed);
+            }
+        }
+        world.on_chunks_edited(&report.affected_chunks);
+        report
+    }
+}
+
+/// collects the previous value of every block an edit changes
+pub struct EditRecorder {
+    batch: Option<EditBatch>,
+}
+
+impl EditRecorder {
+    pub fn new(world: usize) -> Self {
+        Self {
+            batch: Some(EditBatch {
+                world,
+                chunks: Vec::new(),
+            }),
+        }
+    }
+
+    /// a recorder that keeps nothing, for edits that shouldn't be undoable
+    pub fn disabled() -> Self {
+        Self { batch: None }
+    }
+
+    fn record(&mut self, chunk_position: IVec3, local: IVec3, previous: VoxelBlock) {
+        let Some(batch) = self.batch.as_mut() else {
+            return;
+        };
+        // edits walk one chunk at a time, so only the last entry can match
+        if batch.chunks.last().is_none_or(|c| c.position != chunk_position) {
+            batch.chunks.push(ChunkEdit::new(chunk_position));
+        }
+        batch.chunks.last_mut().unwrap().push(local, previous);
+    }
+
+    pub fn finish(self) -> Option<EditBatch> {
+        self.batch.filter(|batch| !batch.is_empty())
+    }
+}
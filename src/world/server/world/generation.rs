@@ -4,7 +4,9 @@ use glam::IVec3;
 use rayon::iter::IntoParallelIterator;
 use rayon::iter::ParallelIterator;
 use rustc_hash::FxHashSet;
+use std::fmt;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use crate::world::server::world::{WorldGenerator, CHUNK_DIM};
 use crate::world::server::world::chunk::VoxelChunk;
 
@@ -13,6 +15,8 @@ pub type VoxelChunkNoise = [[[f32; CHUNK_DIM]; CHUNK_DIM]; CHUNK_DIM];
 pub struct WorldGenHandle<G: WorldGenerator> {
     master_generator: G,
     pending: FxHashSet<IVec3>,
+    generated: usize,
+    busy: Duration,
 
     thread: Option<JoinHandle<()>>,
     sender: Option<Sender<WorldGenRequest>>,
@@ -24,6 +28,8 @@ impl<G: WorldGenerator> WorldGenHandle<G> {
         Self {
             master_generator: world_generator,
             pending: FxHashSet::default(),
+            generated: 0,
+            busy: Duration::ZERO,
             sender: None,
             receiver: None,
             thread: None,
@@ -64,10 +70,12 @@ impl<G: WorldGenerator> WorldGenHandle<G> {
 
     pub fn try_recv(&mut self) -> Result<WorldGenResponse, RecvError> {
         if let Ok(response) = self.receiver.as_ref().unwrap().try_recv() {
-            if let WorldGenResponse::Chunks(chunks) = &response {
+            if let WorldGenResponse::Chunks(chunks, elapsed) = &response {
                 for chunk in chunks {
                     self.pending.remove(&chunk.position);
                 }
+                self.generated += chunks.len();
+                self.busy += *elapsed;
             }
             return Ok(response);
         }
@@ -77,10 +85,38 @@ impl<G: WorldGenerator> WorldGenHandle<G> {
     pub fn is_pending(&self, chunk_pos: &IVec3) -> bool {
         self.pending.contains(chunk_pos)
     }
+
+    pub fn stats(&self) -> GenerationStats {
+        GenerationStats {
+            loaded: 0,
+            generated: self.generated,
+            pending: self.pending.len(),
+            busy: self.busy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GenerationStats {
+    pub loaded: usize,    // chunks in memory, generated or read back from storage
+    pub generated: usize, // since the world was opened
+    pub pending: usize,
+    pub busy: Duration, // spent generating, summed over batches
+}
+
+impl fmt::Display for GenerationStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_chunk = self.busy.as_secs_f64() * 1000.0 / self.generated.max(1) as f64;
+        write!(
+            f,
+            "{} chunks loaded, {} generated ({:.2}ms per chunk), {} pending",
+            self.loaded, self.generated, per_chunk, self.pending
+        )
+    }
 }
 
 pub enum WorldGenResponse {
-    Chunks(Vec<VoxelChunk>),
+    Chunks(Vec<VoxelChunk>, Duration), // and how long the batch took
     RecvError(RecvError),
     Term,
 }
@@ -105,11 +141,13 @@ fn world_gen_task<G: WorldGenerator>(
 
         match request {
             WorldGenRequest::Chunks(positions) => {
+                let started = Instant::now();
                 let chunks: Vec<VoxelChunk> = positions
                     .into_par_iter()
                     .map(|chunk_pos| world_generator.chunk(chunk_pos))
                     .collect();
-                sender.send(WorldGenResponse::Chunks(chunks)).unwrap();
+                let response = WorldGenResponse::Chunks(chunks, started.elapsed());
+                sender.send(response).unwrap();
             }
             WorldGenRequest::Term => {
                 sender.send(WorldGenResponse::Term).unwrap();
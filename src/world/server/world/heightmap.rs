@@ -0,0 +1,112 @@
+use crate::world::server::world::CHUNK_DIM;
+use crate::world::server::world::chunk::VoxelChunk;
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec2, IVec3};
+use rustc_hash::FxHashMap;
+
+pub const NO_HEIGHT: i32 = i32::MIN;
+
+/// world y of the highest solid block for every (x, z) of a chunk column, indexed [x][z]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct ColumnHeightmap {
+    pub heights: [[i32; CHUNK_DIM]; CHUNK_DIM],
+    pub min_chunk_y: i32, // range of chunks seen in this column, heights only cover these
+    pub max_chunk_y: i32,
+}
+
+impl ColumnHeightmap {
+    fn new(chunk_y: i32) -> Self {
+        Self {
+            heights: [[NO_HEIGHT; CHUNK_DIM]; CHUNK_DIM],
+            min_chunk_y: chunk_y,
+            max_chunk_y: chunk_y,
+        }
+    }
+
+    pub fn height(&self, local_x: usize, local_z: usize) -> Option<i32> {
+        let height = self.heights[local_x][local_z];
+        (height != NO_HEIGHT).then_some(height)
+    }
+}
+
+#[derive(Default)]
+pub struct Heightmaps {
+    columns: FxHashMap<IVec2, ColumnHeightmap>,
+}
+
+impl Heightmaps {
+    pub fn column_of(chunk_position: IVec3) -> IVec2 {
+        IVec2::new(chunk_position.x, chunk_position.z)
+    }
+
+    pub fn column(&self, column: IVec2) -> Option<&ColumnHeightmap> {
+        self.columns.get(&column)
+    }
+
+    /// highest solid block at world (x, z) among the chunks seen so far
+    pub fn height_at(&self, x: i32, z: i32) -> Option<i32> {
+        let dim = CHUNK_DIM as i32;
+        let column = IVec2::new(x.div_euclid(dim), z.div_euclid(dim));
+        self.columns
+            .get(&column)?
+            .height(x.rem_euclid(dim) as usize, z.rem_euclid(dim) as usize)
+    }
+
+    /// inserts a heightmap as-is, e.g. one read back from storage
+    pub fn insert(&mut self, column: IVec2, heightmap: ColumnHeightmap) {
+        self.columns.insert(column, heightmap);
+    }
+
+    /// raises the column's heights to include a newly generated chunk
+    pub fn on_chunk_loaded(&mut self, chunk: &VoxelChunk) {
+        let chunk_y = chunk.position.y;
+        let heightmap = self
+            .columns
+            .entry(Self::column_of(chunk.position))
+            .or_insert_with(|| ColumnHeightmap::new(chunk_y));
+        heightmap.min_chunk_y = heightmap.min_chunk_y.min(chunk_y);
+        heightmap.max_chunk_y = heightmap.max_chunk_y.max(chunk_y);
+        if chunk.is_empty() {
+            return;
+        }
+        for x in 0..CHUNK_DIM {
+            for z in 0..CHUNK_DIM {
+                if let Some(height) = chunk_top(chunk, x, z) {
+                    let slot = &mut heightmap.heights[x][z];
+                    *slot = (*slot).max(height);
+                }
+            }
+        }
+    }
+
+    /// recomputes the heights an edit inside `chunk_position` could have changed
+    pub fn on_chunk_edited(&mut self, chunk_position: IVec3, chunks: &FxHashMap<IVec3, VoxelChunk>) {
+        let Some(heightmap) = self.columns.get_mut(&Self::column_of(chunk_position)) else {
+            return;
+        };
+        let chunk_top_y = (chunk_position.y + 1) * CHUNK_DIM as i32;
+        for x in 0..CHUNK_DIM {
+            for z in 0..CHUNK_DIM {
+                if heightmap.heights[x][z] >= chunk_top_y {
+                    continue; // something above the edited chunk still covers this column
+                }
+                heightmap.heights[x][z] = (heightmap.min_chunk_y..=chunk_position.y)
+                    .rev()
+                    .filter_map(|y| {
+                        let position = IVec3::new(chunk_position.x, y, chunk_position.z);
+                        chunks.get(&position)
+                    })
+                    .find_map(|chunk| chunk_top(chunk, x, z))
+                    .unwrap_or(NO_HEIGHT);
+            }
+        }
+    }
+}
+
+fn chunk_top(chunk: &VoxelChunk, x: usize, z: usize) -> Option<i32> {
+    let local_top = (0..CHUNK_DIM)
+        .rev()
+        .find(|&y| !chunk.blocks[x][y][z].is_transparent())?;
+    Some(chunk.position.y * CHUNK_DIM as i32 + local_top as i32)
+}
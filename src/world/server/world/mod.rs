@@ -3,13 +3,23 @@ pub mod generation;
 mod earth_gen;
 pub mod chunk;
 pub mod block;
+pub mod edit;
+pub mod heightmap;
+pub mod storage;
+pub mod spawn;
 
 use fastnoise2::generator::GeneratorWrapper;
 use fastnoise2::{Node, SafeNode};
 use glam::{IVec3, USizeVec3};
+use std::io;
 pub use earth::Earth;
 use crate::world::server::world::block::VoxelBlock;
 use crate::world::server::world::chunk::VoxelChunk;
+use crate::world::server::world::generation::GenerationStats;
+use crate::world::server::world::heightmap::Heightmaps;
+use crate::world::server::world::spawn::WorldMeta;
+use crate::world::entity::EntityStore;
+use crate::world::time::WorldClock;
 
 pub const CHUNK_DIM: usize = 16;
 pub const CHUNK_DIM_HALF: usize = CHUNK_DIM / 2;
@@ -26,12 +36,25 @@ pub struct WorldConfig {
     pub max_world_size: USizeVec3,
 }
 
-pub trait World {
-    fn tick(&mut self);
+pub trait World: Send {
+    fn tick(&mut self, dt: f32);
     fn request_chunks(&mut self, positions: &[IVec3]) -> Vec<&VoxelChunk>;
+    fn chunk(&self, position: IVec3) -> Option<&VoxelChunk>;
+    fn chunk_mut(&mut self, position: IVec3) -> Option<&mut VoxelChunk>;
+    fn heightmaps(&self) -> &Heightmaps;
+    /// must be called after blocks of loaded chunks were changed in place
+    fn on_chunks_edited(&mut self, positions: &[IVec3]);
+    fn entities(&self) -> &EntityStore;
+    fn entities_mut(&mut self) -> &mut EntityStore;
+    fn clock(&self) -> &WorldClock;
+    fn clock_mut(&mut self) -> &mut WorldClock;
+    fn meta(&self) -> &WorldMeta;
+    fn meta_mut(&mut self) -> &mut WorldMeta;
     fn request_chunk_generation(&mut self);
+    fn generation_stats(&self) -> GenerationStats;
     fn start_simulation(&mut self);
     fn stop_simulation(&mut self);
+    fn save(&mut self) -> io::Result<()>;
 }
 
 pub trait WorldGenerator: Clone + Send + Sync + 'static {
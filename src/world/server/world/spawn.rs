@@ -0,0 +1,52 @@
+use crate::compute::geo::IVec3Iter;
+use crate::world::physics::VoxelCollider;
+use crate::world::server::world::{CHUNK_DIM, World};
+use glam::{IVec2, IVec3, Vec3};
+
+pub const VOID_LEVEL: f32 = -256.0; // players below this are respawned
+
+const SPAWN_SEARCH_RADIUS: i32 = 2; // in chunks around the origin
+const SPAWN_SEARCH_MIN_CHUNK_Y: i32 = -4;
+const SPAWN_SEARCH_MAX_CHUNK_Y: i32 = 4;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WorldMeta {
+    pub spawn: Option<Vec3>, // feet position, chosen once the spawn area is generated
+}
+
+fn spawn_search_chunks() -> IVec3Iter {
+    let r = SPAWN_SEARCH_RADIUS;
+    IVec3Iter::new(
+        -r..r + 1,
+        SPAWN_SEARCH_MIN_CHUNK_Y..SPAWN_SEARCH_MAX_CHUNK_Y + 1,
+        -r..r + 1,
+    )
+}
+
+/// picks solid ground with two free blocks above it, closest to the origin first,
+/// returns None until every chunk of the search area is generated
+pub fn find_spawn(world: &mut dyn World) -> Option<Vec3> {
+    let search_chunks: Vec<IVec3> = spawn_search_chunks().collect();
+    if world.request_chunks(&search_chunks).len() != search_chunks.len() {
+        return None;
+    }
+    let world: &dyn World = world;
+
+    let r = SPAWN_SEARCH_RADIUS * CHUNK_DIM as i32;
+    let mut columns: Vec<IVec2> = (-r..=r)
+        .flat_map(|x| (-r..=r).map(move |z| IVec2::new(x, z)))
+        .collect();
+    columns.sort_by_key(|column| column.length_squared());
+
+    let spawn = columns.into_iter().find_map(|column| {
+        let ground = world.heightmaps().height_at(column.x, column.y)?;
+        let feet = IVec3::new(column.x, ground + 1, column.y);
+        let has_room = [feet, feet + IVec3::Y]
+            .iter()
+            .all(|voxel| world.block_at(*voxel).is_some_and(|b| b.is_transparent()));
+        has_room.then(|| feet.as_vec3() + Vec3::new(0.5, 0.0, 0.5))
+    });
+    // no ground anywhere near the origin, drop the player in from the top of the search area
+    let fallback_y = ((SPAWN_SEARCH_MAX_CHUNK_Y + 1) * CHUNK_DIM as i32) as f32;
+    Some(spawn.unwrap_or(Vec3::new(0.5, fallback_y, 0.5)))
+}
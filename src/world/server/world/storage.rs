@@ -0,0 +1,269 @@
+use crate::world::server::world::VoxelChunkBlocks;
+use crate::world::server::world::chunk::VoxelChunk;
+use crate::world::server::world::heightmap::ColumnHeightmap;
+use crate::world::server::world::spawn::WorldMeta;
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec2, IVec3, Vec3};
+use std::io;
+use std::path::{Path, PathBuf};
+
+const COLUMN_FILE_MAGIC: [u8; 4] = *b"VXCL";
+const META_FILE_MAGIC: [u8; 4] = *b"VXMT";
+const STORAGE_FORMAT_VERSION: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ColumnFileHeader {
+    magic: [u8; 4],
+    version: u32,
+    chunk_count: u32,
+    _pad: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ChunkRecord {
+    y: i32,
+    voxel_count: u32,
+    blocks: VoxelChunkBlocks,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct MetaRecord {
+    magic: [u8; 4],
+    version: u32,
+    spawn: Vec3,
+    has_spawn: u32,
+}
+
+/// a saved chunk column, its heightmap is stored alongside so it never has to be rebuilt on load
+pub struct StoredColumn {
+    pub heightmap: ColumnHeightmap,
+    pub chunks: Vec<VoxelChunk>,
+}
+
+/// on-disk world data, one file per chunk column: header, heightmap, then chunk records.
+/// everything is written in native byte order, saves don't load on machines of the other endianness
+pub struct WorldStorage {
+    directory: PathBuf,
+}
+
+impl WorldStorage {
+    pub fn open(directory: impl AsRef<Path>) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        std::fs::create_dir_all(directory.join("columns"))?;
+        Ok(Self { directory })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn column_path(&self, column: IVec2) -> PathBuf {
+        self.directory
+            .join("columns")
+            .join(format!("{}.{}.col", column.x, column.y))
+    }
+
+    pub fn save_meta(&self, meta: &WorldMeta) -> io::Result<()> {
+        let record = MetaRecord {
+            magic: META_FILE_MAGIC,
+            version: STORAGE_FORMAT_VERSION,
+            spawn: meta.spawn.unwrap_or_default(),
+            has_spawn: meta.spawn.is_some() as u32,
+        };
+        // write then rename, like columns
+        let path = self.directory.join("meta.bin");
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, bytemuck::bytes_of(&record))?;
+        std::fs::rename(tmp_path, path)
+    }
+
+    pub fn load_meta(&self) -> io::Result<Option<WorldMeta>> {
+        let bytes = match std::fs::read(self.directory.join("meta.bin")) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let record: MetaRecord = bytemuck::try_pod_read_unaligned(&bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "truncated meta file"))?;
+        if record.magic != META_FILE_MAGIC || record.version != STORAGE_FORMAT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported meta file"));
+        }
+        Ok(Some(WorldMeta {
+            spawn: (record.has_spawn != 0).then_some(record.spawn),
+        }))
+    }
+
+    pub fn save_column<'a>(
+        &self,
+        column: IVec2,
+        heightmap: &ColumnHeightmap,
+        chunks: impl Iterator<Item = &'a VoxelChunk>,
+    ) -> io::Result<()> {
+        let mut records = Vec::new();
+        for chunk in chunks {
+            let record = ChunkRecord {
+                y: chunk.position.y,
+                voxel_count: chunk.voxel_count,
+                blocks: chunk.blocks,
+            };
+            records.extend_from_slice(bytemuck::bytes_of(&record));
+        }
+        let header = ColumnFileHeader {
+            magic: COLUMN_FILE_MAGIC,
+            version: STORAGE_FORMAT_VERSION,
+            chunk_count: (records.len() / size_of::<ChunkRecord>()) as u32,
+            _pad: 0,
+        };
+
+        let mut bytes = Vec::with_capacity(
+            size_of::<ColumnFileHeader>() + size_of::<ColumnHeightmap>() + records.len(),
+        );
+        bytes.extend_from_slice(bytemuck::bytes_of(&header));
+        bytes.extend_from_slice(bytemuck::bytes_of(heightmap));
+        bytes.extend_from_slice(&records);
+
+        // write then rename, so a crash mid-save never leaves a torn column behind
+        let path = self.column_path(column);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, bytes)?;
+        std::fs::rename(tmp_path, path)
+    }
+
+    pub fn load_column(&self, column: IVec2) -> io::Result<Option<StoredColumn>> {
+        let bytes = match std::fs::read(self.column_path(column)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+
+        let header_end = size_of::<ColumnFileHeader>();
+        let heightmap_end = header_end + size_of::<ColumnHeightmap>();
+        if bytes.len() < heightmap_end {
+            return Err(invalid("truncated column file"));
+        }
+        let header: ColumnFileHeader = bytemuck::pod_read_unaligned(&bytes[..header_end]);
+        if header.magic != COLUMN_FILE_MAGIC {
+            return Err(invalid("not a column file"));
+        }
+        if header.version != STORAGE_FORMAT_VERSION {
+            return Err(invalid("unsupported column file version"));
+        }
+        let records = &bytes[heightmap_end..];
+        if records.len() != header.chunk_count as usize * size_of::<ChunkRecord>() {
+            return Err(invalid("column file chunk count mismatch"));
+        }
+
+        let heightmap = bytemuck::pod_read_unaligned(&bytes[header_end..heightmap_end]);
+        let chunks = records
+            .chunks_exact(size_of::<ChunkRecord>())
+            .map(|record_bytes| {
+                let record: ChunkRecord = bytemuck::pod_read_unaligned(record_bytes);
+                let position = IVec3::new(column.x, record.y, column.y);
+                VoxelChunk::new(position, record.blocks, record.voxel_count)
+            })
+            .collect();
+        Ok(Some(StoredColumn { heightmap, chunks }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::server::block::VoxelBlock;
+    use crate::world::server::world::CHUNK_DIM;
+    use crate::world::server::world::heightmap::Heightmaps;
+
+    const COLUMN: IVec2 = IVec2::new(3, -2);
+
+    /// a fresh directory for each test, they run in parallel
+    fn storage(name: &str) -> WorldStorage {
+        let directory =
+            std::env::temp_dir().join(format!("voxer-storage-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        WorldStorage::open(directory).unwrap()
+    }
+
+    fn chunk(y: i32, height: usize) -> VoxelChunk {
+        let mut chunk = VoxelChunk::new(IVec3::new(COLUMN.x, y, COLUMN.y), Zeroable::zeroed(), 0);
+        for x in 0..CHUNK_DIM {
+            for z in 0..CHUNK_DIM {
+                for local_y in 0..height.min(x + 1) {
+                    chunk.blocks[x][local_y][z] = VoxelBlock { value: 1 << 15 };
+                    chunk.voxel_count += 1;
+                }
+            }
+        }
+        chunk
+    }
+
+    fn saved_column(storage: &WorldStorage) -> (ColumnHeightmap, Vec<VoxelChunk>) {
+        let chunks = vec![chunk(-1, CHUNK_DIM), chunk(0, 5)];
+        let mut heightmaps = Heightmaps::default();
+        for chunk in &chunks {
+            heightmaps.on_chunk_loaded(chunk);
+        }
+        let heightmap = *heightmaps.column(COLUMN).unwrap();
+        storage
+            .save_column(COLUMN, &heightmap, chunks.iter())
+            .unwrap();
+        (heightmap, chunks)
+    }
+
+    /// saves a column, changes the bytes at `offset` and loads it again
+    fn load_corrupted(name: &str, offset: usize, bytes: &[u8]) -> io::Error {
+        let storage = storage(name);
+        saved_column(&storage);
+        let path = storage.column_path(COLUMN);
+        let mut file = std::fs::read(&path).unwrap();
+        file[offset..offset + bytes.len()].copy_from_slice(bytes);
+        std::fs::write(&path, file).unwrap();
+        let error = storage.load_column(COLUMN).err().unwrap();
+        std::fs::remove_dir_all(storage.directory()).unwrap();
+        error
+    }
+
+    #[test]
+    fn round_trip() {
+        let storage = storage("round-trip");
+        let (heightmap, chunks) = saved_column(&storage);
+        let loaded = storage.load_column(COLUMN).unwrap().unwrap();
+        assert_eq!(
+            bytemuck::bytes_of(&loaded.heightmap),
+            bytemuck::bytes_of(&heightmap)
+        );
+        assert_eq!(loaded.chunks.len(), chunks.len());
+        for (loaded, saved) in loaded.chunks.iter().zip(&chunks) {
+            assert_eq!(loaded.position, saved.position);
+            assert_eq!(loaded.voxel_count, saved.voxel_count);
+            assert_eq!(
+                bytemuck::bytes_of(&loaded.blocks),
+                bytemuck::bytes_of(&saved.blocks)
+            );
+        }
+        assert!(storage.load_column(COLUMN + IVec2::X).unwrap().is_none());
+        std::fs::remove_dir_all(storage.directory()).unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let error = load_corrupted("magic", 0, b"NOPE");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_bad_version() {
+        let version = STORAGE_FORMAT_VERSION + 1;
+        let error = load_corrupted("version", 4, &version.to_ne_bytes());
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_bad_chunk_count() {
+        let error = load_corrupted("chunk-count", 8, &3u32.to_ne_bytes());
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
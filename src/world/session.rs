@@ -1,8 +1,9 @@
-use glam::Vec3;
+use glam::{Quat, Vec3};
 
 pub(crate) struct PlayerLocation {
     pub world: usize,
     pub position: Vec3,
+    pub rotation: Quat, // where the player is looking
 }
 
 pub(crate) struct PlayerSession {
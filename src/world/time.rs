@@ -0,0 +1,95 @@
+use crate::compute::timestep::FixedTimestep;
+use glam::Vec3;
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+pub const WORLD_TICK_RATE: u32 = 20;
+pub const TICKS_PER_DAY: u64 = 24000; // tick 0 is sunrise, a quarter day later is noon
+
+const DAY_SKY_COLOR: Vec3 = Vec3::new(0.45, 0.68, 1.0);
+const NIGHT_SKY_COLOR: Vec3 = Vec3::new(0.005, 0.01, 0.04);
+const DUSK_SKY_COLOR: Vec3 = Vec3::new(0.9, 0.45, 0.2);
+const MIN_AMBIENT_LIGHT: f32 = 0.15;
+const MAX_AMBIENT_LIGHT: f32 = 0.55;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorldTime {
+    pub ticks: u64,
+    pub frozen: bool,
+}
+
+impl WorldTime {
+    pub fn time_of_day(&self) -> u64 {
+        self.ticks % TICKS_PER_DAY
+    }
+
+    pub fn day(&self) -> u64 {
+        self.ticks / TICKS_PER_DAY
+    }
+
+    /// 0..1 through the current day
+    pub fn day_fraction(&self) -> f32 {
+        self.time_of_day() as f32 / TICKS_PER_DAY as f32
+    }
+
+    pub fn sky(&self) -> Sky {
+        Sky::at(self.day_fraction())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sky {
+    pub color: Vec3,
+    pub sun_direction: Vec3, // towards the sun
+    pub ambient_light: f32,
+}
+
+impl Sky {
+    pub fn at(day_fraction: f32) -> Self {
+        let angle = day_fraction * TAU;
+        let sun_direction = Vec3::new(angle.cos(), angle.sin(), 0.25).normalize();
+        let daylight = smoothstep(-0.1, 0.25, sun_direction.y);
+        let dusk = (1.0 - sun_direction.y.abs() * 4.0).clamp(0.0, 1.0) * 0.5;
+
+        let color = NIGHT_SKY_COLOR.lerp(DAY_SKY_COLOR, daylight).lerp(DUSK_SKY_COLOR, dusk);
+        let ambient_light = MIN_AMBIENT_LIGHT + (MAX_AMBIENT_LIGHT - MIN_AMBIENT_LIGHT) * daylight;
+        Self {
+            color,
+            sun_direction,
+            ambient_light,
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// advances a world time at `WORLD_TICK_RATE` regardless of how often it is ticked
+pub struct WorldClock {
+    pub time: WorldTime,
+    step: FixedTimestep,
+}
+
+impl WorldClock {
+    pub fn new(time: WorldTime) -> Self {
+        Self {
+            time,
+            step: FixedTimestep::new(WORLD_TICK_RATE, u32::MAX),
+        }
+    }
+
+    pub fn tick(&mut self, elapsed: Duration) {
+        let (ticks, _) = self.step.advance(elapsed);
+        if !self.time.frozen {
+            self.time.ticks += ticks as u64;
+        }
+    }
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self::new(WorldTime::default())
+    }
+}